@@ -0,0 +1,61 @@
+use crate::config;
+use crate::config::settings::Settings;
+use crate::constants;
+use crate::models::{health, network_transversal, response, room as room_models, supervisor};
+use crate::service::{
+    network_transversal as network_transversal_service, room as room_service, webrtc,
+};
+
+use actix::Addr;
+use actix_web::{web, HttpResponse};
+use std::time::Duration;
+
+const READINESS_DEADLINE: Duration = Duration::from_secs(2);
+
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(response::ResponseBody::Message(constants::MESSAGE_ALIVE))
+}
+
+pub async fn readyz(
+    settings: web::Data<Settings>,
+    refresher_address: web::Data<Addr<network_transversal_service::Refresher>>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
+    room_address: web::Data<Addr<room_service::Room>>,
+) -> HttpResponse {
+    let missing_plugins = config::gstreamer::missing_plugins(&settings.media.plugins);
+    let room = room_address
+        .get_ref()
+        .send(room_models::GetRooms {})
+        .timeout(READINESS_DEADLINE)
+        .await
+        .is_ok();
+    let supervisor = webrtc_address
+        .get_ref()
+        .send(supervisor::Ping {})
+        .timeout(READINESS_DEADLINE)
+        .await
+        .is_ok();
+    let ice_servers = match refresher_address
+        .get_ref()
+        .send(network_transversal::GetStatus {})
+        .timeout(READINESS_DEADLINE)
+        .await
+    {
+        Ok(status) => status.ice_servers.len(),
+        Err(_) => 0,
+    };
+
+    let readiness = health::Readiness {
+        ready: missing_plugins.is_empty() && room && supervisor && ice_servers > 0,
+        missing_plugins,
+        room,
+        supervisor,
+        ice_servers,
+    };
+
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
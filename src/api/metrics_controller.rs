@@ -0,0 +1,10 @@
+use crate::service::metrics;
+
+use actix_web::HttpResponse;
+use prometheus::TEXT_FORMAT;
+
+pub async fn get_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(TEXT_FORMAT)
+        .body(metrics::encode())
+}
@@ -1,3 +1,6 @@
+pub mod health_controller;
+pub mod metrics_controller;
 pub mod nat_controller;
+pub mod recording_controller;
 pub mod room_controller;
 pub mod static_web_controller;
\ No newline at end of file
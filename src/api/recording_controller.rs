@@ -0,0 +1,66 @@
+use crate::config::settings::Settings;
+use crate::models::{error, recording, response};
+use crate::service::{recording as recording_service, webrtc};
+use std::path::Path;
+
+use actix::Addr;
+use actix_files::NamedFile;
+use actix_web::{web, HttpResponse};
+
+pub async fn start_recording(
+    room_request: web::Path<recording::GetRecordings>,
+    request: Option<web::Json<recording::StartRecordingRequest>>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
+) -> Result<HttpResponse, error::WigglypuffError> {
+    let mixed = request.and_then(|request| request.mixed);
+    let manifest = webrtc_address
+        .get_ref()
+        .send(recording::StartRecording {
+            room_name: room_request.into_inner().name,
+            mixed,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(response::ResponseBody::Recording(manifest)))
+}
+
+pub async fn stop_recording(
+    room_request: web::Path<recording::GetRecordings>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
+) -> Result<HttpResponse, error::WigglypuffError> {
+    let manifest = webrtc_address
+        .get_ref()
+        .send(recording::StopRecording {
+            room_name: room_request.into_inner().name,
+        })
+        .await??;
+
+    Ok(HttpResponse::Ok().json(response::ResponseBody::Recording(manifest)))
+}
+
+pub async fn get_recordings(
+    room_request: web::Path<recording::GetRecordings>,
+    settings: web::Data<Settings>,
+) -> Result<HttpResponse, error::WigglypuffError> {
+    let recordings = recording_service::finished_recordings(
+        Path::new(&settings.recording.directory),
+        &room_request.name,
+    );
+
+    Ok(HttpResponse::Ok().json(response::ResponseBody::Recordings(recordings)))
+}
+
+pub async fn get_recording_file(
+    file_request: web::Path<recording::GetRecordingFile>,
+    settings: web::Data<Settings>,
+) -> Result<NamedFile, error::WigglypuffError> {
+    let path = recording_service::recording_file(
+        Path::new(&settings.recording.directory),
+        &file_request.name,
+        &file_request.id,
+        &file_request.file,
+    )
+    .ok_or(error::WigglypuffError::NotFound)?;
+
+    NamedFile::open(path).map_err(|_| error::WigglypuffError::NotFound)
+}
@@ -1,30 +1,39 @@
 use crate::constants;
-use crate::models::{error, response, room as room_models, supervisor};
-use crate::service::{room as room_service, session, webrtc};
+use crate::models::{error, response, room as room_models, supervisor, webrtc as webrtc_models};
+use crate::service::{authentication, metrics, room as room_service, session, webrtc};
 
 use actix::Addr;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use std::time::Instant;
 
 pub async fn create(
     request: web::Json<room_models::CreateRoom>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
     room_address: web::Data<Addr<room_service::Room>>,
 ) -> Result<HttpResponse, error::WigglypuffError> {
-    let is_unique = room_address
+    room_address
         .get_ref()
         .send(room_models::CreateRoom {
             name: request.name.to_owned(),
             master_uuid: request.master_uuid.to_owned(),
+            pairing: request.pairing,
+            transport: request.transport,
         })
-        .await?;
+        .await??;
 
-    if is_unique {
-        Ok(HttpResponse::Ok().json(response::ResponseBody::Message(
-            constants::MESSAGE_ROOM_CREATED,
-        )))
-    } else {
-        Err(error::WigglypuffError::RoomAlreadyExist)
-    }
+    webrtc_address.get_ref().do_send(supervisor::ConfigureRoom {
+        room_name: request.name.to_owned(),
+        media: supervisor::RoomMedia {
+            master_uuid: request.master_uuid.to_owned(),
+            pairing: request.pairing,
+            transport: request.transport,
+        },
+    });
+
+    Ok(HttpResponse::Ok().json(response::ResponseBody::Message(
+        constants::MESSAGE_ROOM_CREATED,
+    )))
 }
 
 pub async fn get_rooms(
@@ -38,8 +47,8 @@ pub async fn get_rooms(
     Ok(HttpResponse::Ok().json(response::ResponseBody::Rooms(rooms)))
 }
 
-pub async fn get_user_by_room(
-    room_request: web::Path<room_models::GetUsers>,
+pub async fn get_room_state(
+    room_request: web::Path<room_models::GetRoomState>,
     room_address: web::Data<Addr<room_service::Room>>,
 ) -> Result<HttpResponse, error::WigglypuffError> {
     let room = room_request.into_inner();
@@ -53,8 +62,8 @@ pub async fn get_user_by_room(
         .unwrap();
 
     if &master_uuid != "NAN" {
-        let users = room_address.get_ref().send(room).await?;
-        Ok(HttpResponse::Ok().json(response::ResponseBody::Users(users)))
+        let room_state = room_address.get_ref().send(room).await?;
+        Ok(HttpResponse::Ok().json(response::ResponseBody::RoomState(room_state)))
     } else {
         Ok(
             HttpResponse::Forbidden().json(response::ResponseBody::Message(
@@ -64,13 +73,71 @@ pub async fn get_user_by_room(
     }
 }
 
+pub async fn get_heartbeats(
+    room_request: web::Path<room_models::GetHeartbeats>,
+    room_address: web::Data<Addr<room_service::Room>>,
+) -> Result<HttpResponse, error::WigglypuffError> {
+    let room = room_request.into_inner();
+
+    let master_uuid = room_address
+        .get_ref()
+        .send(room_models::GetMaster {
+            room_name: room.name.clone(),
+        })
+        .await
+        .unwrap();
+
+    if &master_uuid != "NAN" {
+        let heartbeats = room_address.get_ref().send(room).await?;
+        Ok(HttpResponse::Ok().json(response::ResponseBody::Heartbeats(heartbeats)))
+    } else {
+        Ok(
+            HttpResponse::Forbidden().json(response::ResponseBody::Message(
+                constants::MESSAGE_ROOM_DOESNT_EXIST,
+            )),
+        )
+    }
+}
+
+pub async fn get_stats(
+    room_request: web::Path<webrtc_models::GetStats>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
+    room_address: web::Data<Addr<room_service::Room>>,
+) -> Result<HttpResponse, error::WigglypuffError> {
+    let room = room_request.into_inner();
+
+    let master_uuid = room_address
+        .get_ref()
+        .send(room_models::GetMaster {
+            room_name: room.name.clone(),
+        })
+        .await
+        .unwrap();
+
+    if &master_uuid != "NAN" {
+        let stats = webrtc_address.get_ref().send(room).await?;
+        Ok(HttpResponse::Ok().json(response::ResponseBody::Stats(stats)))
+    } else {
+        Ok(
+            HttpResponse::Forbidden().json(response::ResponseBody::Message(
+                constants::MESSAGE_ROOM_DOESNT_EXIST,
+            )),
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn join(
     room: web::Path<room_models::Join>,
+    join_query: web::Query<room_models::JoinQuery>,
     request: HttpRequest,
     stream: web::Payload,
+    token_verifier: web::Data<authentication::JoinTokenVerifier>,
+    heartbeat: web::Data<session::Heartbeat>,
     webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
     room_address: web::Data<Addr<room_service::Room>>,
 ) -> Result<HttpResponse, Error> {
+    let join_started = Instant::now();
     let master_uuid = room_address
         .get_ref()
         .send(room_models::GetMaster {
@@ -80,31 +147,114 @@ pub async fn join(
         .unwrap();
 
     if &master_uuid != "NAN" {
+        sentry::configure_scope(|scope| {
+            scope.set_tag("room", &room.room_name);
+            scope.set_tag("uuid", &room.uuid);
+            scope.set_tag(
+                "role",
+                match room.uuid == master_uuid {
+                    true => "master",
+                    false => "student",
+                },
+            );
+        });
+        let token = match &join_query.token {
+            Some(token) => Some(token.as_str()),
+            None => authentication::bearer_token(request.headers()),
+        };
+        token_verifier
+            .get_ref()
+            .verify(token, &room.room_name, &room.uuid, &master_uuid)?;
+
+        let has_capacity = room_address
+            .get_ref()
+            .send(room_models::CheckCapacity {
+                room_name: room.room_name.clone(),
+                uuid: room.uuid.clone(),
+            })
+            .await
+            .unwrap();
+        if !has_capacity {
+            return Ok(
+                HttpResponse::Forbidden().json(response::ResponseBody::Message(
+                    constants::MESSAGE_ROOM_FULL,
+                )),
+            );
+        }
+
+        let resume_token = match &join_query.resume {
+            Some(resume_token) => {
+                let is_resumed = room_address
+                    .get_ref()
+                    .send(room_models::Resume {
+                        room_name: room.room_name.clone(),
+                        uuid: room.uuid.clone(),
+                        resume_token: resume_token.clone(),
+                    })
+                    .await
+                    .unwrap();
+                match is_resumed {
+                    true => Some(resume_token.clone()),
+                    false => None,
+                }
+            }
+            None => None,
+        };
+
         let response = ws::start(
             session::Session {
                 room_name: room.room_name.to_owned(),
                 uuid: room.uuid.to_owned(),
+                session_id: authentication::random_token(),
+                resume_token: resume_token.clone(),
                 room_address: room_address.get_ref().clone(),
-                master_uuid: master_uuid,
+                master_uuid,
                 webrtc_address: webrtc_address.get_ref().clone(),
+                heartbeat: *heartbeat.get_ref(),
+                last_seen: Instant::now(),
+                ping_sent: None,
+                leaving: false,
             },
             &request,
             stream,
         );
 
         if response.is_ok() {
-            webrtc_address
-                .get_ref()
-                .send(supervisor::RegisterUser {
-                    room_address: room_address.get_ref().clone(),
-                    room_name: room.room_name.clone(),
-                    uuid: room.uuid.to_owned(),
-                })
-                .await
-                .unwrap();
+            if resume_token.is_none() {
+                let muted = room_address
+                    .get_ref()
+                    .send(room_models::JoinMuted {
+                        room_name: room.room_name.clone(),
+                        uuid: room.uuid.clone(),
+                    })
+                    .await
+                    .unwrap();
+                webrtc_address
+                    .get_ref()
+                    .send(supervisor::RegisterUser {
+                        room_address: room_address.get_ref().clone(),
+                        room_name: room.room_name.clone(),
+                        uuid: room.uuid.to_owned(),
+                        client_offers: join_query.offer.unwrap_or(false),
+                        muted,
+                    })
+                    .await
+                    .unwrap();
+            }
+            metrics::JOIN_LATENCY.observe(join_started.elapsed().as_secs_f64());
 
             response
         } else {
+            if let Some(resume_token) = resume_token {
+                room_address.get_ref().do_send(room_models::Disconnect {
+                    room_name: room.room_name.clone(),
+                    uuid: room.uuid.clone(),
+                    session_id: resume_token,
+                    webrtc_address: webrtc_address.get_ref().clone(),
+                    leaving: false,
+                });
+            }
+
             Ok(
                 HttpResponse::Forbidden().json(response::ResponseBody::Message(
                     constants::MESSAGE_USER_NOT_WEBSOCKET,
@@ -122,6 +272,7 @@ pub async fn join(
 
 pub async fn delete_room(
     room: web::Json<room_models::DeleteRoom>,
+    webrtc_address: web::Data<Addr<webrtc::supervisor::Supervisor>>,
     room_address: web::Data<Addr<room_service::Room>>,
 ) -> Result<HttpResponse, error::WigglypuffError> {
     room_address
@@ -131,6 +282,13 @@ pub async fn delete_room(
         })
         .await?;
 
+    webrtc_address
+        .get_ref()
+        .send(supervisor::DeleteRoom {
+            room_name: room.name.to_owned(),
+        })
+        .await?;
+
     Ok(HttpResponse::Ok().json(response::ResponseBody::Message(
         constants::MESSAGE_ROOM_DELETED,
     )))
@@ -1,14 +1,21 @@
-use std::path::PathBuf;
+use crate::config::settings::Settings;
+use std::path::Path;
 
 use actix_files::NamedFile;
-use actix_web::HttpRequest;
+use actix_web::{web, HttpRequest};
 
-pub async fn get_webrtc_client(_: HttpRequest) -> actix_web::Result<NamedFile> {
-    let path: PathBuf = "./static/index.html".parse().unwrap();
+pub async fn get_webrtc_client(
+    _: HttpRequest,
+    settings: web::Data<Settings>,
+) -> actix_web::Result<NamedFile> {
+    let path = Path::new(&settings.server.static_dir).join("index.html");
     Ok(NamedFile::open(path)?)
 }
 
-pub async fn get_webrtc_js(_: HttpRequest) -> actix_web::Result<NamedFile> {
-    let path: PathBuf = "./static/webrtc.js".parse().unwrap();
+pub async fn get_webrtc_js(
+    _: HttpRequest,
+    settings: web::Data<Settings>,
+) -> actix_web::Result<NamedFile> {
+    let path = Path::new(&settings.server.static_dir).join("webrtc.js");
     Ok(NamedFile::open(path)?)
 }
@@ -1,28 +1,50 @@
-use crate::api::{nat_controller, room_controller, static_web_controller};
+use crate::api::{
+    health_controller, metrics_controller, nat_controller, recording_controller, room_controller,
+    static_web_controller,
+};
 use crate::constants;
+use crate::service::authentication;
 
+use actix_web::dev::Service;
 use actix_web::{error, guard, web, HttpResponse};
+use futures::future::{self, Either};
 use log::info;
 
 pub fn config_services(config: &mut web::ServiceConfig) {
     info!("[CONFIGURATING ACTOR ROUTE]");
     config
+        // called by room clients with their join token, so it sits outside the API key scope
+        .service(
+            web::resource("/api/room/{name}/ice/{uuid}")
+                .route(web::get().to(nat_controller::get_user_ice_servers)),
+        )
         .service(
             web::scope("/api")
+                .wrap_fn(
+                    |request, service| match authentication::authorize_api_key(&request) {
+                        Ok(()) => Either::Left(service.call(request)),
+                        Err(err) => Either::Right(future::err(err.into())),
+                    },
+                )
                 .service(
                     web::scope("/info")
                         .service(
-                            web::scope("/network_transversal").service(
-                                web::resource("")
-                                    .route(web::get().to(nat_controller::get_stun_address)),
-                            ),
+                            web::scope("/network_transversal")
+                                .service(
+                                    web::resource("")
+                                        .route(web::get().to(nat_controller::get_stun_address)),
+                                )
+                                .service(web::resource("/status").route(
+                                    web::get().to(nat_controller::get_network_transversal_status),
+                                )),
                         )
                         .service(
                             web::scope("/refresh/network_transversal").service(
-                                web::resource("")
-                                    .route(web::post().to(nat_controller::refresh_twilio)),
+                                web::resource("").route(
+                                    web::post().to(nat_controller::refresh_network_transversal),
+                                ),
                             ),
-                        )
+                        ),
                 )
                 .service(
                     web::scope("/user").service(
@@ -36,10 +58,44 @@ pub fn config_services(config: &mut web::ServiceConfig) {
                 .service(
                     web::scope("/room")
                         .service(
-                            web::scope("/{name}").service(
-                                web::resource("")
-                                    .route(web::get().to(room_controller::get_user_by_room)),
-                            ),
+                            web::scope("/{name}")
+                                .service(
+                                    web::resource("")
+                                        .route(web::get().to(room_controller::get_room_state)),
+                                )
+                                .service(
+                                    web::scope("/heartbeat").service(web::resource("").route(
+                                        web::get().to(room_controller::get_heartbeats),
+                                    )),
+                                )
+                                .service(
+                                    web::scope("/stats").service(
+                                        web::resource("")
+                                            .route(web::get().to(room_controller::get_stats)),
+                                    ),
+                                )
+                                .service(
+                                    web::scope("/recording").service(
+                                        web::resource("")
+                                            .route(
+                                                web::post()
+                                                    .to(recording_controller::start_recording),
+                                            )
+                                            .route(
+                                                web::delete()
+                                                    .to(recording_controller::stop_recording),
+                                            ),
+                                    ),
+                                )
+                                .service(
+                                    web::scope("/recordings")
+                                        .service(web::resource("").route(
+                                            web::get().to(recording_controller::get_recordings),
+                                        ))
+                                        .service(web::resource("/{id}/{file}").route(
+                                            web::get().to(recording_controller::get_recording_file),
+                                        )),
+                                ),
                         )
                         .service(
                             web::resource("")
@@ -57,6 +113,18 @@ pub fn config_services(config: &mut web::ServiceConfig) {
                         ),
                 ),
         )
+        .service(
+            web::scope("/healthz")
+                .service(web::resource("").route(web::get().to(health_controller::healthz))),
+        )
+        .service(
+            web::scope("/readyz")
+                .service(web::resource("").route(web::get().to(health_controller::readyz))),
+        )
+        .service(
+            web::scope("/metrics")
+                .service(web::resource("").route(web::get().to(metrics_controller::get_metrics))),
+        )
         .service(
             web::scope("/websocket/{room_name}/{uuid}")
                 .service(web::resource("").route(web::get().to(room_controller::join))),
@@ -1,17 +1,20 @@
-use crate::constants;
 use gstreamer;
 use log::info;
 
-pub fn check_plugins() {
+pub fn check_plugins(plugins: &[String]) -> Vec<String> {
     gstreamer::init().unwrap();
 
-    let plugin = constants::PLUGIN_WEBRTC;
+    let missing = missing_plugins(plugins);
+    info!("Missing plugins: {:?}", missing);
+
+    missing
+}
+
+pub fn missing_plugins(plugins: &[String]) -> Vec<String> {
     let registry = gstreamer::Registry::get();
-    let missing = plugin
+    plugins
         .iter()
         .filter(|n| registry.find_plugin(n).is_none())
         .cloned()
-        .collect::<Vec<_>>();
-
-    info!("Missing plugins: {:?}", missing);
+        .collect::<Vec<_>>()
 }
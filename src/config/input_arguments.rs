@@ -1,26 +1,453 @@
-use clap::{crate_authors, crate_version, Arg};
+use crate::config::settings::{ApiKey, LogFormat, NatProviderKind, Settings};
+use crate::models::authentication::ApiScope;
+
+use clap::{crate_authors, crate_version, Arg, ArgMatches};
 use env_logger;
+use std::io::Write;
+use std::str::FromStr;
+
+const DEFAULT_CONFIG_FILE: &str = "wigglypuff.toml";
 
-pub fn config_arguments() -> String {
+pub struct Arguments {
+    pub settings: Result<Settings, Vec<String>>,
+    pub check_config: bool,
+}
+
+pub fn config_arguments() -> Arguments {
     let arguments = clap::App::new("Wigglypuff")
         .version(crate_version!())
         .author(crate_authors!())
         .about("Service for reading wigglypuff sura and ayat")
         .args(&[
+            Arg::with_name("config")
+                .long("config")
+                .help("Set TOML configuration file, environment variables and flags override it")
+                .env("WIGGLYPUFF_CONFIG"),
+            Arg::with_name("check_config")
+                .long("check-config")
+                .help("Validate the configuration and exit"),
             Arg::with_name("host")
+                .long("host")
                 .help("Set wigglypuff-service host address")
                 .env("HOST"),
             Arg::with_name("port")
+                .long("port")
                 .help("Set wigglypuff-service port")
                 .env("PORT"),
+            Arg::with_name("host_position")
+                .index(1)
+                .value_name("HOST")
+                .help("Set wigglypuff-service host address, same as --host"),
+            Arg::with_name("port_position")
+                .index(2)
+                .value_name("PORT")
+                .help("Set wigglypuff-service port, same as --port"),
+            Arg::with_name("static_dir")
+                .long("static-dir")
+                .help("Set directory of the static web client files")
+                .env("STATIC_DIR"),
+            Arg::with_name("log_level")
+                .long("log-level")
+                .help("Set log filter, e.g. info,actix_web=debug")
+                .env("LOG_LEVEL"),
+            Arg::with_name("log_format")
+                .long("log-format")
+                .help("Set log output format")
+                .env("LOG_FORMAT")
+                .possible_values(&["text", "json"]),
+            Arg::with_name("sentry_dsn")
+                .long("sentry-dsn")
+                .help("Set Sentry DSN, Sentry is disabled when empty")
+                .env("SENTRY_DSN"),
+            Arg::with_name("room_grace_period")
+                .long("room-grace-period")
+                .help("Set seconds to keep an empty room pipeline alive before destroying it")
+                .env("ROOM_GRACE_PERIOD"),
+            Arg::with_name("session_grace_period")
+                .long("session-grace-period")
+                .help("Set seconds a disconnected websocket session can be resumed")
+                .env("SESSION_GRACE_PERIOD"),
+            Arg::with_name("heartbeat_interval")
+                .long("heartbeat-interval")
+                .help("Set seconds between websocket pings sent to each session")
+                .env("HEARTBEAT_INTERVAL"),
+            Arg::with_name("heartbeat_timeout")
+                .long("heartbeat-timeout")
+                .help("Set seconds without pong or message before a websocket session is closed")
+                .env("HEARTBEAT_TIMEOUT"),
+            Arg::with_name("max_rooms")
+                .long("max-rooms")
+                .help("Set maximum number of rooms")
+                .env("MAX_ROOMS"),
+            Arg::with_name("max_participants")
+                .long("max-participants")
+                .help("Set maximum number of participants in a room")
+                .env("MAX_PARTICIPANTS"),
+            Arg::with_name("stats_interval")
+                .long("stats-interval")
+                .help("Set seconds between WebRTC stats collections in each room")
+                .env("STATS_INTERVAL"),
+            Arg::with_name("stats_push")
+                .long("stats-push")
+                .help("Set whether collected WebRTC stats are pushed to the room master websocket")
+                .env("STATS_PUSH")
+                .possible_values(&["true", "false"]),
+            Arg::with_name("speaker_detection")
+                .long("speaker-detection")
+                .help("Set whether active speakers are detected and broadcast to each room")
+                .env("SPEAKER_DETECTION")
+                .possible_values(&["true", "false"]),
+            Arg::with_name("speaker_interval")
+                .long("speaker-interval")
+                .help("Set milliseconds between audio level broadcasts in each room")
+                .env("SPEAKER_INTERVAL"),
+            Arg::with_name("recording_dir")
+                .long("recording-dir")
+                .help("Set directory where room recordings are written")
+                .env("RECORDING_DIR"),
+            Arg::with_name("recording_mixed")
+                .long("recording-mixed")
+                .help("Set whether recordings include a mixed track by default")
+                .env("RECORDING_MIXED")
+                .possible_values(&["true", "false"]),
+            Arg::with_name("require_plugins")
+                .long("require-plugins")
+                .help("Set whether startup fails when required GStreamer plugins are missing")
+                .env("REQUIRE_PLUGINS")
+                .possible_values(&["true", "false"]),
+            Arg::with_name("opus_bitrate")
+                .long("opus-bitrate")
+                .help("Set bitrate in bit/s of the opus encoder")
+                .env("OPUS_BITRATE"),
+            Arg::with_name("rtp_payload_type")
+                .long("rtp-payload-type")
+                .help("Set RTP payload type of the opus stream")
+                .env("RTP_PAYLOAD_TYPE"),
+            Arg::with_name("nat_provider")
+                .long("nat-provider")
+                .help("Set ICE server provider")
+                .env("NAT_PROVIDER")
+                .possible_values(&["static", "coturn", "twilio"]),
+            Arg::with_name("ice_servers")
+                .long("ice-servers")
+                .help("Set ICE servers as JSON list for the static provider")
+                .env("ICE_SERVERS"),
+            Arg::with_name("coturn_urls")
+                .long("coturn-urls")
+                .help("Set comma separated STUN/TURN urls for the coturn provider")
+                .env("COTURN_URLS"),
+            Arg::with_name("coturn_secret")
+                .long("coturn-secret")
+                .help("Set coturn static-auth-secret for the coturn provider")
+                .env("COTURN_SECRET"),
+            Arg::with_name("coturn_ttl")
+                .long("coturn-ttl")
+                .help("Set seconds until coturn credentials expire")
+                .env("COTURN_TTL"),
+            Arg::with_name("coturn_user_ttl")
+                .long("coturn-user-ttl")
+                .help("Set seconds until per-user coturn credentials expire")
+                .env("COTURN_USER_TTL"),
+            Arg::with_name("twilio_sid")
+                .long("twilio-sid")
+                .help("Set Twilio account SID for the twilio provider")
+                .env("TWILIO_SID"),
+            Arg::with_name("twilio_token")
+                .long("twilio-token")
+                .help("Set Twilio auth token for the twilio provider")
+                .env("TWILIO_TOKEN"),
+            Arg::with_name("join_token_key")
+                .long("join-token-key")
+                .help("Set HS256 key used to verify websocket join tokens")
+                .env("JOIN_TOKEN_KEY"),
+            Arg::with_name("api_keys")
+                .long("api-keys")
+                .help("Set comma separated REST API keys as KEY:SCOPE[+SCOPE], scopes are read-only, room-admin and nat-admin")
+                .env("API_KEYS"),
         ])
         .get_matches();
 
-    std::env::set_var("RUST_LOG", "info,actix_web=debug");
-    env_logger::init();
-    let host = arguments.value_of("host").unwrap();
-    let port = arguments.value_of("port").unwrap();
-    let url = format!("{}:{}", &host, &port);
+    let settings = load_settings(&arguments).and_then(|settings| {
+        let errors = settings.validate();
+        match errors.is_empty() {
+            true => Ok(settings),
+            false => Err(errors),
+        }
+    });
+    if let Ok(settings) = &settings {
+        init_logger(settings);
+    }
+
+    Arguments {
+        settings,
+        check_config: arguments.is_present("check_config"),
+    }
+}
+
+fn load_settings(arguments: &ArgMatches) -> Result<Settings, Vec<String>> {
+    let mut settings = match arguments.value_of("config") {
+        Some(path) => read_settings(path)?,
+        None => match std::path::Path::new(DEFAULT_CONFIG_FILE).exists() {
+            true => read_settings(DEFAULT_CONFIG_FILE)?,
+            false => Settings::default(),
+        },
+    };
+
+    let mut errors = Vec::new();
+    let mut overrides = Overrides {
+        arguments,
+        errors: &mut errors,
+    };
+
+    // `wigglypuff HOST PORT` keeps working next to the --host and --port flags
+    let host = arguments
+        .value_of("host_position")
+        .or_else(|| arguments.value_of("host"));
+    let port = arguments
+        .value_of("port_position")
+        .or_else(|| arguments.value_of("port"));
+    if host.is_some() || port.is_some() {
+        let (default_host, default_port) = match settings.server.bind.first() {
+            Some(address) => match address.rfind(':') {
+                Some(index) => (
+                    address[..index].to_string(),
+                    address[index + 1..].to_string(),
+                ),
+                None => (address.clone(), String::new()),
+            },
+            None => ("0.0.0.0".to_string(), "8080".to_string()),
+        };
+        settings.server.bind = vec![format!(
+            "{}:{}",
+            host.map(String::from).unwrap_or(default_host),
+            port.map(String::from).unwrap_or(default_port)
+        )];
+    }
+    overrides.string("static_dir", &mut settings.server.static_dir);
+    overrides.parse("require_plugins", &mut settings.server.require_plugins);
+
+    overrides.string("log_level", &mut settings.log.level);
+    if let Some(log_format) = arguments.value_of("log_format") {
+        settings.log.format = match log_format {
+            "json" => LogFormat::Json,
+            _ => LogFormat::Text,
+        };
+    }
+
+    if let Some(sentry_dsn) = arguments.value_of("sentry_dsn") {
+        settings.sentry.dsn = match sentry_dsn.is_empty() {
+            true => None,
+            false => Some(sentry_dsn.to_string()),
+        };
+    }
+
+    overrides.parse("room_grace_period", &mut settings.room.grace_period);
+    overrides.parse(
+        "session_grace_period",
+        &mut settings.room.session_grace_period,
+    );
+    overrides.parse("heartbeat_interval", &mut settings.room.heartbeat_interval);
+    overrides.parse("heartbeat_timeout", &mut settings.room.heartbeat_timeout);
+    overrides.optional("max_rooms", &mut settings.room.max_rooms);
+    overrides.optional("max_participants", &mut settings.room.max_participants);
+
+    overrides.parse("stats_interval", &mut settings.stats.interval);
+    overrides.parse("stats_push", &mut settings.stats.push_to_master);
+
+    overrides.parse("speaker_detection", &mut settings.speaker.enabled);
+    overrides.parse("speaker_interval", &mut settings.speaker.interval_ms);
+
+    overrides.string("recording_dir", &mut settings.recording.directory);
+    overrides.parse("recording_mixed", &mut settings.recording.mixed);
+
+    overrides.parse("opus_bitrate", &mut settings.media.opus_bitrate);
+    overrides.parse("rtp_payload_type", &mut settings.media.rtp_payload_type);
+
+    if let Some(nat_provider) = arguments.value_of("nat_provider") {
+        settings.nat.provider = match nat_provider {
+            "static" => NatProviderKind::Static,
+            "coturn" => NatProviderKind::Coturn,
+            _ => NatProviderKind::Twilio,
+        };
+    }
+    if let Some(ice_servers) = arguments.value_of("ice_servers") {
+        match serde_json::from_str(ice_servers) {
+            Ok(ice_servers) => settings.nat.ice_servers = ice_servers,
+            Err(_) => overrides
+                .errors
+                .push("ice-servers must be a JSON list of ICE servers".to_string()),
+        }
+    }
+    if let Some(coturn_urls) = arguments.value_of("coturn_urls") {
+        settings.nat.coturn.urls = coturn_urls
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+    }
+    overrides.optional("coturn_secret", &mut settings.nat.coturn.secret);
+    overrides.parse("coturn_ttl", &mut settings.nat.coturn.ttl);
+    overrides.parse("coturn_user_ttl", &mut settings.nat.coturn.user_ttl);
+    overrides.optional("twilio_sid", &mut settings.nat.twilio.sid);
+    overrides.optional("twilio_token", &mut settings.nat.twilio.token);
+
+    overrides.optional("join_token_key", &mut settings.auth.join_token_key);
+    if let Some(api_keys) = arguments.value_of("api_keys") {
+        match api_keys_arguments(api_keys) {
+            Ok(api_keys) => settings.auth.api_keys = api_keys,
+            Err(err) => overrides.errors.push(err),
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(settings),
+        false => Err(errors),
+    }
+}
+
+fn read_settings(path: &str) -> Result<Settings, Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| vec![format!("can't read config file {}: {}", path, err)])?;
+    toml::from_str(&content).map_err(|err| vec![format!("invalid config file {}: {}", path, err)])
+}
+
+struct Overrides<'a> {
+    arguments: &'a ArgMatches<'a>,
+    errors: &'a mut Vec<String>,
+}
+
+impl<'a> Overrides<'a> {
+    fn string(&mut self, name: &str, setting: &mut String) {
+        if let Some(value) = self.arguments.value_of(name) {
+            *setting = value.to_string();
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, setting: &mut T) {
+        if let Some(value) = self.arguments.value_of(name) {
+            match value.parse::<T>() {
+                Ok(value) => *setting = value,
+                Err(_) => self.errors.push(format!(
+                    "{} has an invalid value: {}",
+                    name.replace('_', "-"),
+                    value
+                )),
+            }
+        }
+    }
+
+    fn optional<T: FromStr>(&mut self, name: &str, setting: &mut Option<T>) {
+        if let Some(value) = self.arguments.value_of(name) {
+            match value.parse::<T>() {
+                Ok(value) => *setting = Some(value),
+                Err(_) => self.errors.push(format!(
+                    "{} has an invalid value: {}",
+                    name.replace('_', "-"),
+                    value
+                )),
+            }
+        }
+    }
+}
+
+fn api_keys_arguments(api_keys: &str) -> Result<Vec<ApiKey>, String> {
+    api_keys
+        .split(',')
+        .filter(|api_key| !api_key.trim().is_empty())
+        .map(|api_key| {
+            let mut api_key = api_key.trim().splitn(2, ':');
+            let key = api_key.next().unwrap().to_string();
+            let scopes = api_key
+                .next()
+                .ok_or_else(|| "api-keys must be formatted as KEY:SCOPE[+SCOPE]".to_string())?
+                .split('+')
+                .map(|scope| scope.parse())
+                .collect::<Result<Vec<ApiScope>, String>>()?;
+            Ok(ApiKey { key, scopes })
+        })
+        .collect()
+}
+
+fn init_logger(settings: &Settings) {
+    let mut logger = env_logger::Builder::new();
+    logger.parse_filters(&settings.log.level);
+    if settings.log.format == LogFormat::Json {
+        logger.format(|buffer, record| {
+            writeln!(
+                buffer,
+                "{}",
+                serde_json::json!({
+                    "timestamp": buffer.timestamp().to_string(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                })
+            )
+        });
+    }
+    logger.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_keys_are_parsed_with_their_scopes() {
+        let api_keys = api_keys_arguments("admin:room-admin+nat-admin, reader:read-only,").unwrap();
+        assert_eq!(api_keys.len(), 2);
+        assert_eq!(api_keys[0].key, "admin");
+        assert_eq!(
+            api_keys[0].scopes,
+            vec![ApiScope::RoomAdmin, ApiScope::NatAdmin]
+        );
+        assert_eq!(api_keys[1].key, "reader");
+        assert_eq!(api_keys[1].scopes, vec![ApiScope::ReadOnly]);
+    }
+
+    #[test]
+    fn malformed_api_keys_are_rejected() {
+        assert_eq!(
+            api_keys_arguments("admin").err().unwrap(),
+            "api-keys must be formatted as KEY:SCOPE[+SCOPE]"
+        );
+        assert_eq!(
+            api_keys_arguments("admin:room-admin+root").err().unwrap(),
+            "unknown api key scope: root"
+        );
+        assert!(api_keys_arguments("admin:").is_err());
+    }
+
+    #[test]
+    fn invalid_flag_values_are_collected() {
+        let arguments = clap::App::new("wigglypuff")
+            .args(&[
+                Arg::with_name("heartbeat_timeout")
+                    .long("heartbeat-timeout")
+                    .takes_value(true),
+                Arg::with_name("max_rooms")
+                    .long("max-rooms")
+                    .takes_value(true),
+            ])
+            .get_matches_from(vec![
+                "wigglypuff",
+                "--heartbeat-timeout",
+                "soon",
+                "--max-rooms",
+                "12",
+            ]);
+        let mut errors = Vec::new();
+        let mut overrides = Overrides {
+            arguments: &arguments,
+            errors: &mut errors,
+        };
+        let mut heartbeat_timeout = 15u64;
+        let mut max_rooms = None;
+        overrides.parse("heartbeat_timeout", &mut heartbeat_timeout);
+        overrides.optional("max_rooms", &mut max_rooms);
 
-    url
+        assert_eq!(heartbeat_timeout, 15);
+        assert_eq!(max_rooms, Some(12usize));
+        assert_eq!(errors, vec!["heartbeat-timeout has an invalid value: soon"]);
+    }
 }
@@ -1,3 +1,4 @@
 pub mod app;
 pub mod input_arguments;
 pub mod gstreamer;
+pub mod settings;
@@ -0,0 +1,408 @@
+use crate::models::authentication::ApiScope;
+use crate::models::network_transversal;
+use crate::service::network_transversal::NatProvider;
+use crate::service::webrtc::channel::SpeakerDetection;
+
+use serde::Deserialize;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: Server,
+    pub log: Log,
+    pub sentry: Sentry,
+    pub nat: Nat,
+    pub auth: Auth,
+    pub room: Room,
+    pub stats: Stats,
+    pub speaker: Speaker,
+    pub media: Media,
+    pub recording: Recording,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Server {
+    pub bind: Vec<String>,
+    pub static_dir: String,
+    pub require_plugins: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sentry {
+    pub dsn: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NatProviderKind {
+    Static,
+    Coturn,
+    #[default]
+    Twilio,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Nat {
+    pub provider: NatProviderKind,
+    pub ice_servers: Vec<network_transversal::STUNTURN>,
+    pub coturn: Coturn,
+    pub twilio: Twilio,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Coturn {
+    pub urls: Vec<String>,
+    pub secret: Option<String>,
+    pub ttl: u64,
+    pub user_ttl: u64,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Twilio {
+    pub sid: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    pub join_token_key: Option<String>,
+    pub api_keys: Vec<ApiKey>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    pub key: String,
+    pub scopes: Vec<ApiScope>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Room {
+    pub grace_period: u64,
+    pub session_grace_period: u64,
+    pub heartbeat_interval: u64,
+    pub heartbeat_timeout: u64,
+    pub max_rooms: Option<usize>,
+    pub max_participants: Option<usize>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Stats {
+    pub interval: u64,
+    pub push_to_master: bool,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Speaker {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    pub threshold_db: f64,
+    pub margin_db: f64,
+    pub hold_ms: u64,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Media {
+    pub plugins: Vec<String>,
+    pub opus_bitrate: i32,
+    pub rtp_payload_type: u32,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Recording {
+    pub directory: String,
+    pub mixed: bool,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            bind: vec!["0.0.0.0:8080".to_string()],
+            static_dir: "./static".to_string(),
+            require_plugins: false,
+        }
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Log {
+            level: "info,actix_web=debug".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl Default for Coturn {
+    fn default() -> Self {
+        Coturn {
+            urls: Vec::new(),
+            secret: None,
+            ttl: 86400,
+            user_ttl: 3600,
+        }
+    }
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Room {
+            grace_period: 30,
+            session_grace_period: 10,
+            heartbeat_interval: 5,
+            heartbeat_timeout: 15,
+            max_rooms: None,
+            max_participants: None,
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            interval: 5,
+            push_to_master: false,
+        }
+    }
+}
+
+impl Default for Speaker {
+    fn default() -> Self {
+        Speaker {
+            enabled: false,
+            interval_ms: 500,
+            threshold_db: -50.0,
+            margin_db: 6.0,
+            hold_ms: 1000,
+        }
+    }
+}
+
+impl Default for Media {
+    fn default() -> Self {
+        Media {
+            plugins: vec![
+                "autodetect",
+                "vpx",
+                "webrtc",
+                "nice",
+                "dtls",
+                "srtp",
+                "rtpmanager",
+                "rtp",
+                "audiomixer",
+                "level",
+                "ogg",
+                "opus",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            opus_bitrate: 64000,
+            rtp_payload_type: 97,
+        }
+    }
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Recording {
+            directory: "./recordings".to_string(),
+            mixed: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.server.bind.is_empty() {
+            errors.push("server.bind needs at least one address".to_string());
+        }
+        for address in &self.server.bind {
+            if address.to_socket_addrs().is_err() {
+                errors.push(format!("server.bind address {} is invalid", address));
+            }
+        }
+
+        if let Some(dsn) = &self.sentry.dsn {
+            if dsn.parse::<sentry::types::Dsn>().is_err() {
+                errors.push(format!("sentry.dsn {} is invalid", dsn));
+            }
+        }
+
+        match self.nat.provider {
+            NatProviderKind::Coturn => {
+                if self.nat.coturn.urls.is_empty() {
+                    errors.push("nat.coturn.urls is required for the coturn provider".to_string());
+                }
+                if self.nat.coturn.secret.is_none() {
+                    errors
+                        .push("nat.coturn.secret is required for the coturn provider".to_string());
+                }
+            }
+            NatProviderKind::Static | NatProviderKind::Twilio => {}
+        }
+
+        if self.room.heartbeat_interval == 0 {
+            errors.push("room.heartbeat_interval must be greater than zero".to_string());
+        }
+        if self.room.heartbeat_timeout <= self.room.heartbeat_interval {
+            errors.push(
+                "room.heartbeat_timeout must be longer than room.heartbeat_interval".to_string(),
+            );
+        }
+        if self.stats.interval == 0 {
+            errors.push("stats.interval must be greater than zero".to_string());
+        }
+        if self.speaker.interval_ms < 50 {
+            errors.push("speaker.interval_ms must be at least 50".to_string());
+        }
+        if self.speaker.threshold_db > 0.0 {
+            errors.push("speaker.threshold_db must be at most 0 dB".to_string());
+        }
+        if self.speaker.margin_db < 0.0 {
+            errors.push("speaker.margin_db must not be negative".to_string());
+        }
+        if self.room.max_participants == Some(0) {
+            errors.push("room.max_participants must be greater than zero".to_string());
+        }
+        if self.media.opus_bitrate < 4000 || self.media.opus_bitrate > 650000 {
+            errors.push("media.opus_bitrate must be between 4000 and 650000".to_string());
+        }
+        if self.media.rtp_payload_type < 96 || self.media.rtp_payload_type > 127 {
+            errors
+                .push("media.rtp_payload_type must be a dynamic payload type (96-127)".to_string());
+        }
+        if self.recording.directory.is_empty() {
+            errors.push("recording.directory must not be empty".to_string());
+        }
+
+        errors
+    }
+
+    pub fn speaker_detection(&self) -> Option<SpeakerDetection> {
+        match self.speaker.enabled {
+            true => Some(SpeakerDetection {
+                interval: Duration::from_millis(self.speaker.interval_ms),
+                threshold_db: self.speaker.threshold_db,
+                margin_db: self.speaker.margin_db,
+                hold: Duration::from_millis(self.speaker.hold_ms),
+            }),
+            false => None,
+        }
+    }
+
+    pub fn nat_provider(&self) -> NatProvider {
+        match self.nat.provider {
+            NatProviderKind::Static => NatProvider::Static {
+                ice_servers: self.nat.ice_servers.clone(),
+            },
+            NatProviderKind::Coturn => NatProvider::Coturn {
+                urls: self.nat.coturn.urls.clone(),
+                secret: self.nat.coturn.secret.clone().unwrap_or_default(),
+                ttl: Duration::from_secs(self.nat.coturn.ttl),
+                user_ttl: Duration::from_secs(self.nat.coturn.user_ttl),
+            },
+            NatProviderKind::Twilio => NatProvider::Twilio {
+                sid: self.nat.twilio.sid.clone().unwrap_or_default(),
+                token: self.nat.twilio.token.clone().unwrap_or_default(),
+            },
+        }
+    }
+
+    pub fn api_keys(&self) -> Vec<(String, Vec<ApiScope>)> {
+        self.auth
+            .api_keys
+            .iter()
+            .map(|api_key| (api_key.key.clone(), api_key.scopes.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_are_valid() {
+        assert!(Settings::default().validate().is_empty());
+    }
+
+    #[test]
+    fn heartbeat_timeout_must_outlast_interval() {
+        let mut settings = Settings::default();
+        settings.room.heartbeat_interval = 15;
+        settings.room.heartbeat_timeout = 15;
+        assert_eq!(
+            settings.validate(),
+            vec!["room.heartbeat_timeout must be longer than room.heartbeat_interval"]
+        );
+    }
+
+    #[test]
+    fn coturn_provider_needs_a_secret() {
+        let mut settings = Settings::default();
+        settings.nat.provider = NatProviderKind::Coturn;
+        settings.nat.coturn.urls = vec!["turn:turn.example.com:3478".to_string()];
+        assert_eq!(
+            settings.validate(),
+            vec!["nat.coturn.secret is required for the coturn provider"]
+        );
+
+        settings.nat.coturn.secret = Some("north-secret".to_string());
+        assert!(settings.validate().is_empty());
+    }
+
+    #[test]
+    fn rtp_payload_type_must_be_dynamic() {
+        let mut settings = Settings::default();
+        for rtp_payload_type in &[0, 95, 128] {
+            settings.media.rtp_payload_type = *rtp_payload_type;
+            assert_eq!(
+                settings.validate(),
+                vec!["media.rtp_payload_type must be a dynamic payload type (96-127)"]
+            );
+        }
+        for rtp_payload_type in &[96, 127] {
+            settings.media.rtp_payload_type = *rtp_payload_type;
+            assert!(settings.validate().is_empty());
+        }
+    }
+
+    #[test]
+    fn config_file_rejects_unknown_fields() {
+        let settings = toml::from_str::<Settings>("[room]\nheartbeat_interval = 10\n").unwrap();
+        assert_eq!(settings.room.heartbeat_interval, 10);
+        assert_eq!(settings.room.heartbeat_timeout, 15);
+        assert!(toml::from_str::<Settings>("[room]\nheartbeat = 10\n").is_err());
+    }
+}
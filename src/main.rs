@@ -1,5 +1,8 @@
 use actix_web::{App, HttpServer};
+use log::error;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod api;
 mod config;
@@ -9,27 +12,90 @@ mod service;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let url = config::input_arguments::config_arguments();
-    let room = service::room::Room::new();
-    config::gstreamer::check_plugins();
+    let arguments = config::input_arguments::config_arguments();
+    let settings = match arguments.settings {
+        Ok(settings) => settings,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("Invalid configuration: {}", error);
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid configuration",
+            ));
+        }
+    };
+    if arguments.check_config {
+        println!("Configuration is valid");
+        return Ok(());
+    }
 
-    let _sentry_guard = sentry::init((constants::SENTRY_TOKEN, sentry::ClientOptions {
-        release: sentry::release_name!(),
-        ..Default::default()
-    }));
+    let room = service::room::Room::new(
+        Duration::from_secs(settings.room.session_grace_period),
+        settings.room.max_rooms,
+        settings.room.max_participants,
+    );
+    let missing_plugins = config::gstreamer::check_plugins(&settings.media.plugins);
+    if settings.server.require_plugins && !missing_plugins.is_empty() {
+        error!("Refusing to start, missing plugins: {:?}", missing_plugins);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("missing GStreamer plugins: {:?}", missing_plugins),
+        ));
+    }
+
+    let sentry_enabled = settings.sentry.dsn.is_some();
+    let _sentry_guard = settings.sentry.dsn.as_ref().map(|dsn| {
+        sentry::init((
+            dsn.as_str(),
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                ..Default::default()
+            },
+        ))
+    });
 
     let nat = Arc::new(Mutex::new(Vec::<models::network_transversal::STUNTURN>::new()));
-    let webrtc_supervisor = service::webrtc::supervisor::Supervisor::new(nat.clone());
-    HttpServer::new(move || {
+    let nat_refresher =
+        service::network_transversal::Refresher::new(settings.nat_provider(), nat.clone());
+    let token_verifier =
+        service::authentication::JoinTokenVerifier::new(settings.auth.join_token_key.clone());
+    let api_keys = service::authentication::ApiKeys::new(settings.api_keys());
+    let heartbeat = service::session::Heartbeat {
+        interval: Duration::from_secs(settings.room.heartbeat_interval),
+        timeout: Duration::from_secs(settings.room.heartbeat_timeout),
+    };
+    let channel_config = service::webrtc::channel::ChannelConfig {
+        grace_period: Duration::from_secs(settings.room.grace_period),
+        stats_interval: Duration::from_secs(settings.stats.interval),
+        stats_push_to_master: settings.stats.push_to_master,
+        opus_bitrate: settings.media.opus_bitrate,
+        rtp_payload_type: settings.media.rtp_payload_type,
+        speaker_detection: settings.speaker_detection(),
+        recording_directory: PathBuf::from(&settings.recording.directory),
+        recording_mixed: settings.recording.mixed,
+    };
+    let webrtc_supervisor =
+        service::webrtc::supervisor::Supervisor::new(nat.clone(), channel_config);
+    let bind = settings.server.bind.clone();
+    let mut server = HttpServer::new(move || {
         App::new()
+            .data(settings.clone())
             .data(room.clone())
-            .data(nat.clone())
+            .data(nat_refresher.clone())
+            .data(token_verifier.clone())
+            .data(api_keys.clone())
+            .data(heartbeat)
             .data(webrtc_supervisor.clone())
-            .wrap(actix_web::middleware::Logger::default())
-            .wrap(sentry_actix::Sentry::new())
+            .wrap(actix_web::middleware::Logger::new(constants::ACCESS_LOG_FORMAT))
+            .wrap(actix_web::middleware::Condition::new(
+                sentry_enabled,
+                sentry_actix::Sentry::new(),
+            ))
             .configure(config::app::config_services)
-    })
-    .bind(&url)?
-    .run()
-    .await
+    });
+    for address in &bind {
+        server = server.bind(address)?;
+    }
+    server.run().await
 }
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinRole {
+    Master,
+    Student,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct JoinClaims {
+    pub sub: String,
+    pub room: String,
+    pub role: JoinRole,
+    pub exp: usize,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiScope {
+    ReadOnly,
+    RoomAdmin,
+    NatAdmin,
+}
+
+impl std::str::FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read-only" => Ok(ApiScope::ReadOnly),
+            "room-admin" => Ok(ApiScope::RoomAdmin),
+            "nat-admin" => Ok(ApiScope::NatAdmin),
+            _ => Err(format!("unknown api key scope: {}", scope)),
+        }
+    }
+}
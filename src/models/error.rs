@@ -10,12 +10,24 @@ pub enum WigglypuffError {
     NotFound,
     #[error("you are forbidden to access requested file.")]
     Forbidden,
-    #[error("unknown Internal Error")]
-    Unknown,
+    #[error("missing or invalid token")]
+    Unauthorized,
     #[error("unknown Internal Error")]
     MailboxError,
     #[error("room already exist!")]
     RoomAlreadyExist,
+    #[error("maximum number of rooms reached")]
+    RoomLimitReached,
+    #[error("failed to fetch ice servers from nat provider")]
+    NatProvider,
+    #[error("room has no running media channel")]
+    ChannelNotRunning,
+    #[error("room is already being recorded")]
+    RecordingAlreadyStarted,
+    #[error("room is not being recorded")]
+    RecordingNotStarted,
+    #[error("failed to start recording")]
+    RecordingFailed,
 }
 
 #[derive(Serialize)]
@@ -34,15 +46,27 @@ impl ResponseError for WigglypuffError {
         match *self {
             Self::NotFound => StatusCode::NOT_FOUND,
             Self::Forbidden => StatusCode::FORBIDDEN,
-            Self::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::MailboxError => StatusCode::INTERNAL_SERVER_ERROR,
             Self::RoomAlreadyExist => StatusCode::FORBIDDEN,
+            Self::RoomLimitReached => StatusCode::FORBIDDEN,
+            Self::NatProvider => StatusCode::BAD_GATEWAY,
+            Self::ChannelNotRunning => StatusCode::NOT_FOUND,
+            Self::RecordingAlreadyStarted => StatusCode::CONFLICT,
+            Self::RecordingNotStarted => StatusCode::CONFLICT,
+            Self::RecordingFailed => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        sentry::capture_message("Something is not well", Level::Warning);
         let status_code = self.status_code();
+        // client errors are expected, any client could flood Sentry with bad keys or tokens
+        if status_code.is_server_error() {
+            sentry::with_scope(
+                |scope| scope.set_tag("error", format!("{:?}", self)),
+                || sentry::capture_message(&self.to_string(), Level::Error),
+            );
+        }
         let error_response = ErrorResponse {
             message: self.to_string(),
         };
@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    pub ready: bool,
+    pub missing_plugins: Vec<String>,
+    pub room: bool,
+    pub supervisor: bool,
+    pub ice_servers: usize,
+}
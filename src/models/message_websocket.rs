@@ -1,4 +1,6 @@
+use crate::models::{recording, room, webrtc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize)]
 pub struct UserStatus<'a> {
@@ -6,6 +8,38 @@ pub struct UserStatus<'a> {
     pub uuid: &'a str,
 }
 
+#[derive(Serialize)]
+pub struct StatsStatus<'a> {
+    pub action: &'a str,
+    #[serde(flatten)]
+    pub stats: &'a webrtc::ChannelStats,
+}
+
+#[derive(Serialize)]
+pub struct SpeakerStatus<'a> {
+    pub action: &'a str,
+    pub uuid: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+pub struct AudioLevelsStatus<'a> {
+    pub action: &'a str,
+    pub levels: BTreeMap<&'a str, f64>,
+}
+
+#[derive(Serialize)]
+pub struct RecordingStatus<'a> {
+    pub action: &'a str,
+    #[serde(flatten)]
+    pub manifest: &'a recording::Manifest,
+}
+
+#[derive(Serialize)]
+pub struct RecordingError<'a> {
+    pub action: &'a str,
+    pub message: String,
+}
+
 #[derive(PartialEq, Serialize, Deserialize, Clone)]
 #[serde(tag = "action")]
 pub enum MessageSocketType {
@@ -23,6 +57,14 @@ pub enum MessageSocketType {
         uuid: String,
     },
     MuteAllUser {},
+    UnmuteUser {
+        uuid: String,
+    },
+    UnmuteAllUser {},
+    RoomState(room::RoomState),
+    ResumeToken {
+        token: String,
+    },
     MoveSura {
         id_quran: i32,
     },
@@ -42,4 +84,40 @@ pub enum MessageSocketType {
     RequestPair {
         uuid: String,
     },
+    RestartICE {
+        uuid: String,
+    },
+    TrackMap {
+        uuid: String,
+        tracks: BTreeMap<String, String>,
+    },
+    StartRecording {
+        mixed: Option<bool>,
+    },
+    StopRecording {},
+}
+
+impl MessageSocketType {
+    pub fn action(&self) -> &'static str {
+        match self {
+            MessageSocketType::ClickAya { .. } => "ClickAya",
+            MessageSocketType::OfferCorrection { .. } => "OfferCorrection",
+            MessageSocketType::AnswerCorrection { .. } => "AnswerCorrection",
+            MessageSocketType::MuteUser { .. } => "MuteUser",
+            MessageSocketType::MuteAllUser { .. } => "MuteAllUser",
+            MessageSocketType::UnmuteUser { .. } => "UnmuteUser",
+            MessageSocketType::UnmuteAllUser { .. } => "UnmuteAllUser",
+            MessageSocketType::RoomState(..) => "RoomState",
+            MessageSocketType::ResumeToken { .. } => "ResumeToken",
+            MessageSocketType::MoveSura { .. } => "MoveSura",
+            MessageSocketType::ICECandidate { .. } => "ICECandidate",
+            MessageSocketType::SessionDescription { .. } => "SessionDescription",
+            MessageSocketType::WebRTCConnectionState => "WebRTCConnectionState",
+            MessageSocketType::RequestPair { .. } => "RequestPair",
+            MessageSocketType::RestartICE { .. } => "RestartICE",
+            MessageSocketType::TrackMap { .. } => "TrackMap",
+            MessageSocketType::StartRecording { .. } => "StartRecording",
+            MessageSocketType::StopRecording { .. } => "StopRecording",
+        }
+    }
 }
@@ -1,5 +1,8 @@
+pub mod authentication;
+pub mod health;
 pub mod message_websocket;
 pub mod network_transversal;
+pub mod recording;
 pub mod response;
 pub mod room;
 pub mod webrtc;
@@ -0,0 +1,55 @@
+use crate::models::error;
+use actix_derive::Message;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Default)]
+pub struct StartRecordingRequest {
+    pub mixed: Option<bool>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Manifest, error::WigglypuffError>")]
+pub struct StartRecording {
+    pub room_name: String,
+    pub mixed: Option<bool>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Manifest, error::WigglypuffError>")]
+pub struct StopRecording {
+    pub room_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetRecordings {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct GetRecordingFile {
+    pub name: String,
+    pub id: String,
+    pub file: String,
+}
+
+/// Written as `manifest.json` next to the files, times are milliseconds since the unix epoch
+/// and track offsets are relative to `started_at`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub room: String,
+    pub id: String,
+    pub started_at: u64,
+    pub stopped_at: Option<u64>,
+    pub mixed: Option<String>,
+    pub tracks: Vec<ManifestTrack>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestTrack {
+    pub uuid: String,
+    pub file: String,
+    pub start_offset_ms: u64,
+    pub end_offset_ms: Option<u64>,
+}
@@ -5,7 +5,12 @@ use serde::{Deserialize, Serialize};
 pub enum ResponseBody<T> {
     Rooms(T),
     Users(T),
+    RoomState(T),
+    Heartbeats(T),
+    Stats(T),
     IceServers(T),
+    Recording(T),
+    Recordings(T),
     Response(T),
     Message(T),
 }
\ No newline at end of file
@@ -1,12 +1,20 @@
-use actix::Recipient;
+use crate::models::{error, message_websocket, supervisor as supervisor_models};
+use crate::service::webrtc::supervisor;
+use actix::{Addr, Recipient};
 use actix_derive::{Message, MessageResponse};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 #[derive(Message, Deserialize)]
-#[rtype(result = "bool")]
+#[rtype(result = "Result<(), error::WigglypuffError>")]
 pub struct CreateRoom {
     pub name: String,
     pub master_uuid: String,
+    #[serde(default)]
+    pub pairing: supervisor_models::PairingPolicy,
+    #[serde(default)]
+    pub transport: supervisor_models::Transport,
 }
 
 #[derive(Message)]
@@ -24,9 +32,37 @@ pub struct Message(pub String);
 pub struct Connect {
     pub room_name: String,
     pub uuid: String,
+    pub session_id: String,
+    pub resume_token: Option<String>,
     pub session_address: Recipient<Message>,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub room_name: String,
+    pub uuid: String,
+    pub session_id: String,
+    pub webrtc_address: Addr<supervisor::Supervisor>,
+    // closed by the client, the server or a heartbeat timeout, as opposed to a lost transport
+    pub leaving: bool,
+}
+
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct CheckCapacity {
+    pub room_name: String,
+    pub uuid: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Resume {
+    pub room_name: String,
+    pub uuid: String,
+    pub resume_token: String,
+}
+
 #[derive(MessageResponse, Serialize)]
 pub struct Rooms(pub Vec<String>);
 
@@ -50,6 +86,47 @@ pub struct SendUser {
     pub message: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendMaster {
+    pub room_name: String,
+    pub message: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateLesson {
+    pub room_name: String,
+    pub uuid: String,
+    pub data: message_websocket::MessageSocketType,
+}
+
+#[derive(Clone, Default)]
+pub struct Lesson {
+    pub id_quran: Option<i32>,
+    pub aya: Option<i32>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateMute {
+    pub room_name: String,
+    pub from_uuid: String,
+    pub is_master: bool,
+    pub uuid: Option<String>,
+    pub muted: bool,
+    pub message: String,
+    pub webrtc_address: Addr<supervisor::Supervisor>,
+}
+
+/// Asked before a producer is registered, it puts joiners under an active mute-all.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct JoinMuted {
+    pub room_name: String,
+    pub uuid: String,
+}
+
 #[derive(Message, Deserialize)]
 #[rtype(result = "()")]
 pub struct KickUser {
@@ -69,11 +146,48 @@ pub struct Join {
     pub uuid: String,
 }
 
+#[derive(Deserialize)]
+pub struct JoinQuery {
+    pub token: Option<String>,
+    pub resume: Option<String>,
+    pub offer: Option<bool>,
+}
+
+#[derive(MessageResponse, PartialEq, Serialize, Deserialize, Clone)]
+pub struct RoomState {
+    pub master_uuid: String,
+    pub id_quran: Option<i32>,
+    pub aya: Option<i32>,
+    pub muted: Vec<String>,
+    pub participants: Vec<String>,
+}
+
+#[derive(Message, Deserialize)]
+#[rtype(result = "RoomState")]
+pub struct GetRoomState {
+    pub name: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Heartbeat {
+    pub room_name: String,
+    pub uuid: String,
+    pub rtt: Duration,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatStatus {
+    pub rtt_ms: u64,
+    pub last_seen: u64,
+}
+
 #[derive(MessageResponse, Serialize)]
-pub struct Users(pub Vec<String>);
+pub struct Heartbeats(pub BTreeMap<String, HeartbeatStatus>);
 
 #[derive(Message, Deserialize)]
-#[rtype(result = "Users")]
-pub struct GetUsers {
+#[rtype(result = "Heartbeats")]
+pub struct GetHeartbeats {
     pub name: String,
 }
@@ -1,7 +1,8 @@
 use actix_derive::Message;
 use actix::Addr;
 use crate::service::room;
-use serde::Deserialize;
+use crate::service::webrtc::channel;
+use serde::{Deserialize, Serialize};
 
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -9,6 +10,8 @@ pub struct RegisterUser {
     pub uuid: String,
     pub room_name: String,
     pub room_address: Addr<room::Room>,
+    pub client_offers: bool,
+    pub muted: bool,
 }
 
 #[derive(Message, Clone, Deserialize)]
@@ -16,4 +19,59 @@ pub struct RegisterUser {
 pub struct DeleteUser {
     pub uuid: String,
     pub room_name: String,
-}
\ No newline at end of file
+}
+
+#[derive(Message, Clone, Deserialize)]
+#[rtype(result = "()")]
+pub struct DeleteRoom {
+    pub room_name: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ChannelStopped {
+    pub room_name: String,
+    pub channel_address: Addr<channel::Channel>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Ping {}
+
+/// Which consumers the channel builds on its own once a producer's stream arrives.
+/// `teacher-to-all` pairs the master with every student in both directions, `explicit`
+/// leaves pairing to `RequestPair`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PairingPolicy {
+    AllToAll,
+    TeacherToAll,
+    #[default]
+    Explicit,
+}
+
+/// `bundle` carries every remote speaker on the listener's own webrtcbin instead of one
+/// webrtcbin per `src:X_sink:Y` peer, `mcu` sends each listener a single server-side mix
+/// of the speakers they are paired with.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    #[default]
+    PerPeer,
+    Bundle,
+    Mcu,
+}
+
+#[derive(Clone, Default)]
+pub struct RoomMedia {
+    pub master_uuid: String,
+    pub pairing: PairingPolicy,
+    pub transport: Transport,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ConfigureRoom {
+    pub room_name: String,
+    pub media: RoomMedia,
+}
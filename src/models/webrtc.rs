@@ -1,6 +1,9 @@
-use crate::models::{message_websocket, webrtc};
-use actix_derive::Message;
+use crate::models::{message_websocket, recording, webrtc};
+use actix_derive::{Message, MessageResponse};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
 
 #[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub enum Role {
@@ -8,12 +11,60 @@ pub enum Role {
     Producer,
 }
 
+pub struct TeeBranch {
+    pub tee_pad: gstreamer::Pad,
+    pub queue: gstreamer::Element,
+    pub ghost_pad: gstreamer::GhostPad,
+    pub teebin: gstreamer::Bin,
+}
+
+/// A remote speaker carried as an extra sendonly transceiver on the listener's webrtcbin.
+pub struct Track {
+    pub tee_branch: TeeBranch,
+    pub payloader: gstreamer::Element,
+    pub ghost_pad: gstreamer::GhostPad,
+    pub webrtc_pad: gstreamer::Pad,
+    pub transceiver: Option<gstreamer_webrtc::WebRTCRTPTransceiver>,
+}
+
+/// Decodes a producer once so every mix it feeds can share it.
+pub struct Decoder {
+    pub bin: gstreamer::Bin,
+    pub tee_branch: TeeBranch,
+}
+
+pub struct MixInput {
+    pub tee_branch: TeeBranch,
+    pub ghost_pad: gstreamer::GhostPad,
+    pub mixer_pad: gstreamer::Pad,
+}
+
+pub struct Mix {
+    pub bin: gstreamer::Bin,
+    pub inputs: BTreeMap<String, MixInput>,
+}
+
+/// Writes one tee (a producer or the recording mix) to an Ogg/Opus file.
+pub struct Recorder {
+    pub bin: gstreamer::Bin,
+    pub tee_branch: TeeBranch,
+    pub file: String,
+}
+
+pub struct Recording {
+    pub manifest: recording::Manifest,
+    pub directory: PathBuf,
+    pub started: Instant,
+    pub recorders: BTreeMap<String, Recorder>,
+}
+
 pub struct UserPipeline {
     pub fakeaudio: gstreamer::Bin,
     pub webrtcbin: gstreamer::Bin,
     pub tee: gstreamer::Bin,
     pub fakesink: gstreamer::Bin,
     pub role: Role,
+    pub tee_branch: Option<TeeBranch>,
 }
 
 #[derive(Message, Deserialize, Serialize)]
@@ -52,6 +103,80 @@ pub struct RequestPair {
     pub uuid: String,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AudioLevel {
+    pub uuid: String,
+    pub level: f64,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ProducerReady {
+    pub uuid: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RestartICE {
+    pub room_name: String,
+    pub from_uuid: String,
+    pub uuid: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Mute {
+    pub room_name: String,
+    pub uuids: Vec<String>,
+    pub muted: bool,
+}
+
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WebRTCStats {
+    pub ice_connection_state: String,
+    pub round_trip_time: Option<f64>,
+    pub jitter: Option<f64>,
+    pub packets_lost: i64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub is_alive: bool,
+    #[serde(skip)]
+    pub was_connected: bool,
+    #[serde(skip)]
+    pub idle_intervals: u32,
+    #[serde(skip)]
+    pub has_received: bool,
+}
+
+#[derive(MessageResponse, Serialize, Default)]
+pub struct ChannelStats {
+    pub users: BTreeMap<String, WebRTCStats>,
+    pub peers: BTreeMap<String, WebRTCStats>,
+}
+
+#[derive(Message, Deserialize)]
+#[rtype(result = "ChannelStats")]
+pub struct GetStats {
+    pub name: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ConnectionLost {
+    pub uuid: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct PipelineError {
+    pub elements: Vec<String>,
+    pub error: String,
+}
+
 #[derive(Message, Serialize, Deserialize)]
 #[rtype(result = "()")]
 pub struct WigglypuffWebRTC {
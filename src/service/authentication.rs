@@ -0,0 +1,261 @@
+use crate::models::{authentication, error};
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, HeaderMap, Method};
+use actix_web::web;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use log::info;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+#[derive(Clone)]
+pub struct JoinTokenVerifier {
+    key: Option<Vec<u8>>,
+}
+
+impl JoinTokenVerifier {
+    pub fn new(key: Option<String>) -> JoinTokenVerifier {
+        if key.is_none() {
+            info!("[JOIN TOKEN] [NO KEY CONFIGURED] [WEBSOCKET JOINS ARE NOT AUTHENTICATED]");
+        }
+        JoinTokenVerifier {
+            key: key.map(String::into_bytes),
+        }
+    }
+
+    pub fn verify(
+        &self,
+        token: Option<&str>,
+        room_name: &str,
+        uuid: &str,
+        master_uuid: &str,
+    ) -> Result<(), error::WigglypuffError> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let token = token.ok_or(error::WigglypuffError::Unauthorized)?;
+
+        let claims = jsonwebtoken::decode::<authentication::JoinClaims>(
+            token,
+            &DecodingKey::from_secret(key),
+            &Validation::new(Algorithm::HS256),
+        )
+        .map_err(|err| {
+            info!(
+                "[ROOM: {}] [UUID: {}] [INVALID JOIN TOKEN: {}]",
+                room_name, uuid, err
+            );
+            error::WigglypuffError::Unauthorized
+        })?
+        .claims;
+
+        if claims.sub != uuid || claims.room != room_name {
+            return Err(error::WigglypuffError::Forbidden);
+        }
+
+        match (claims.role, uuid == master_uuid) {
+            (authentication::JoinRole::Master, true) => Ok(()),
+            (authentication::JoinRole::Student, false) => Ok(()),
+            _ => Err(error::WigglypuffError::Forbidden),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiKeys {
+    keys: Vec<(String, Vec<authentication::ApiScope>)>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: Vec<(String, Vec<authentication::ApiScope>)>) -> ApiKeys {
+        if keys.is_empty() {
+            info!("[API KEY] [NO KEY CONFIGURED] [REST API IS NOT AUTHENTICATED]");
+        }
+        ApiKeys { keys }
+    }
+
+    fn scopes(&self, api_key: &str) -> Option<&Vec<authentication::ApiScope>> {
+        self.keys
+            .iter()
+            .find(|(key, _)| constant_time_eq(key.as_bytes(), api_key.as_bytes()))
+            .map(|(_, scopes)| scopes)
+    }
+}
+
+pub fn authorize_api_key(request: &ServiceRequest) -> Result<(), error::WigglypuffError> {
+    let api_keys = match request.app_data::<web::Data<ApiKeys>>() {
+        Some(api_keys) if !api_keys.keys.is_empty() => api_keys,
+        _ => return Ok(()),
+    };
+
+    let api_key = request
+        .headers()
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| bearer_token(request.headers()))
+        .ok_or(error::WigglypuffError::Forbidden)?;
+    let scopes = api_keys
+        .scopes(api_key)
+        .ok_or(error::WigglypuffError::Forbidden)?;
+
+    let required_scope = if request.method() == Method::GET || request.method() == Method::HEAD {
+        authentication::ApiScope::ReadOnly
+    } else if request.path().starts_with("/api/info/") {
+        authentication::ApiScope::NatAdmin
+    } else {
+        authentication::ApiScope::RoomAdmin
+    };
+
+    if required_scope == authentication::ApiScope::ReadOnly || scopes.contains(&required_scope) {
+        Ok(())
+    } else {
+        info!(
+            "[API KEY] [{} {}] [MISSING SCOPE: {:?}]",
+            request.method(),
+            request.path(),
+            required_scope
+        );
+        Err(error::WigglypuffError::Forbidden)
+    }
+}
+
+pub fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right.iter())
+            .fold(0, |result, (left, right)| result | (left ^ right))
+            == 0
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn api_keys() -> ApiKeys {
+        ApiKeys::new(vec![
+            (
+                "reader".to_string(),
+                vec![authentication::ApiScope::ReadOnly],
+            ),
+            (
+                "rooms".to_string(),
+                vec![authentication::ApiScope::RoomAdmin],
+            ),
+            (
+                "admin".to_string(),
+                vec![
+                    authentication::ApiScope::RoomAdmin,
+                    authentication::ApiScope::NatAdmin,
+                ],
+            ),
+        ])
+    }
+
+    fn authorize(method: Method, path: &str, api_key: Option<&str>) -> bool {
+        let mut request = TestRequest::default()
+            .method(method)
+            .uri(path)
+            .data(api_keys());
+        if let Some(api_key) = api_key {
+            request = request.header("x-api-key", api_key);
+        }
+        authorize_api_key(&request.to_srv_request()).is_ok()
+    }
+
+    #[test]
+    fn no_configured_keys_allow_everything() {
+        let request = TestRequest::default()
+            .method(Method::DELETE)
+            .uri("/api/room")
+            .data(ApiKeys::new(Vec::new()))
+            .to_srv_request();
+        assert!(authorize_api_key(&request).is_ok());
+    }
+
+    #[test]
+    fn missing_or_unknown_key_is_forbidden() {
+        assert!(!authorize(Method::GET, "/api/room", None));
+        assert!(!authorize(Method::GET, "/api/room", Some("unknown")));
+        assert!(!authorize(Method::PUT, "/api/room", Some("")));
+    }
+
+    #[test]
+    fn any_key_reads() {
+        assert!(authorize(Method::GET, "/api/room", Some("reader")));
+        assert!(authorize(Method::HEAD, "/api/room", Some("reader")));
+        assert!(authorize(
+            Method::GET,
+            "/api/info/network_transversal",
+            Some("rooms")
+        ));
+    }
+
+    #[test]
+    fn mutating_rooms_needs_room_admin() {
+        assert!(!authorize(Method::PUT, "/api/room", Some("reader")));
+        assert!(!authorize(Method::DELETE, "/api/room", Some("reader")));
+        assert!(authorize(Method::PUT, "/api/room", Some("rooms")));
+        assert!(authorize(
+            Method::POST,
+            "/api/room/quran/recording",
+            Some("rooms")
+        ));
+    }
+
+    #[test]
+    fn refreshing_nat_needs_nat_admin() {
+        let path = "/api/info/refresh/network_transversal";
+        assert!(!authorize(Method::POST, path, Some("reader")));
+        assert!(!authorize(Method::POST, path, Some("rooms")));
+        assert!(authorize(Method::POST, path, Some("admin")));
+    }
+
+    #[test]
+    fn bearer_token_is_accepted_as_key() {
+        let request = TestRequest::default()
+            .method(Method::PUT)
+            .uri("/api/room")
+            .header(header::AUTHORIZATION, "Bearer rooms")
+            .data(api_keys())
+            .to_srv_request();
+        assert!(authorize_api_key(&request).is_ok());
+
+        let request = TestRequest::default()
+            .method(Method::PUT)
+            .uri("/api/room")
+            .header(header::AUTHORIZATION, "Basic rooms")
+            .data(api_keys())
+            .to_srv_request();
+        assert!(authorize_api_key(&request).is_err());
+    }
+
+    #[test]
+    fn x_api_key_wins_over_bearer_token() {
+        let request = TestRequest::default()
+            .method(Method::PUT)
+            .uri("/api/room")
+            .header("x-api-key", "reader")
+            .header(header::AUTHORIZATION, "Bearer rooms")
+            .data(api_keys())
+            .to_srv_request();
+        assert!(authorize_api_key(&request).is_err());
+    }
+}
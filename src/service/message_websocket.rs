@@ -1,7 +1,9 @@
 use crate::models::webrtc;
-use crate::models::{message_websocket, room};
+use crate::models::{error, message_websocket, recording, room};
 use crate::service::session;
+use actix::MailboxError;
 use log::info;
+use std::future::Future;
 
 use serde_json;
 
@@ -13,7 +15,18 @@ pub fn broadcast_to_room(
     context.room_address.do_send(room::Broadcast {
         uuid: context.uuid.to_owned(),
         room_name: context.room_name.to_owned(),
-        message: message,
+        message,
+    });
+}
+
+pub fn update_lesson(
+    context: &mut session::Session,
+    message: &message_websocket::MessageSocketType,
+) {
+    context.room_address.do_send(room::UpdateLesson {
+        uuid: context.uuid.to_owned(),
+        room_name: context.room_name.to_owned(),
+        data: message.to_owned(),
     });
 }
 
@@ -25,7 +38,33 @@ pub fn send_to_master(
     context.room_address.do_send(room::SendUser {
         uuid: context.master_uuid.clone(),
         room_name: context.room_name.to_owned(),
-        message: message,
+        message,
+    });
+}
+
+pub fn send_mute_to_room(
+    context: &mut session::Session,
+    message: &message_websocket::MessageSocketType,
+) {
+    let (uuid, muted) = match message {
+        message_websocket::MessageSocketType::MuteUser { uuid } => (Some(uuid.to_owned()), true),
+        message_websocket::MessageSocketType::UnmuteUser { uuid } => (Some(uuid.to_owned()), false),
+        message_websocket::MessageSocketType::MuteAllUser {} => (None, true),
+        message_websocket::MessageSocketType::UnmuteAllUser {} => (None, false),
+        _ => {
+            info!("INCORRECT PATTERN");
+            return;
+        }
+    };
+
+    context.room_address.do_send(room::UpdateMute {
+        room_name: context.room_name.to_owned(),
+        from_uuid: context.uuid.to_owned(),
+        is_master: context.uuid == context.master_uuid,
+        uuid,
+        muted,
+        message: serde_json::to_string(&message).unwrap(),
+        webrtc_address: context.webrtc_address.clone(),
     });
 }
 
@@ -63,8 +102,71 @@ pub fn send_to_client_webrtc(
                 uuid: uuid.to_string(),
             });
         }
+        message_websocket::MessageSocketType::RestartICE { uuid } => {
+            context.webrtc_address.do_send(webrtc::RestartICE {
+                room_name: context.room_name.clone(),
+                from_uuid: context.uuid.clone(),
+                uuid: uuid.to_string(),
+            });
+        }
+        _ => {
+            info!("INCORRECT PATTERN");
+        }
+    }
+}
+
+pub fn send_recording_to_channel(
+    context: &mut session::Session,
+    message: &message_websocket::MessageSocketType,
+) {
+    let room_name = context.room_name.to_owned();
+    match message {
+        message_websocket::MessageSocketType::StartRecording { mixed } => {
+            let response = context.webrtc_address.send(recording::StartRecording {
+                room_name,
+                mixed: mixed.to_owned(),
+            });
+            report_recording_failure(context, response);
+        }
+        message_websocket::MessageSocketType::StopRecording {} => {
+            let response = context
+                .webrtc_address
+                .send(recording::StopRecording { room_name });
+            report_recording_failure(context, response);
+        }
         _ => {
             info!("INCORRECT PATTERN");
         }
     }
 }
+
+// the channel broadcasts started and stopped recordings, only failures go back to the sender
+fn report_recording_failure<F>(context: &mut session::Session, response: F)
+where
+    F: Future<Output = Result<Result<recording::Manifest, error::WigglypuffError>, MailboxError>>
+        + 'static,
+{
+    let room_address = context.room_address.clone();
+    let room_name = context.room_name.to_owned();
+    let uuid = context.uuid.to_owned();
+    actix::spawn(async move {
+        let error = match response.await {
+            Ok(Ok(_)) => return,
+            Ok(Err(error)) => error.to_string(),
+            Err(error) => error.to_string(),
+        };
+        info!(
+            "[ROOM: {}] [UUID: {}] [RECORDING REQUEST FAILED: {}]",
+            room_name, uuid, error
+        );
+        room_address.do_send(room::SendUser {
+            room_name,
+            uuid,
+            message: serde_json::to_string(&message_websocket::RecordingError {
+                action: "RecordingFailed",
+                message: error,
+            })
+            .unwrap(),
+        });
+    });
+}
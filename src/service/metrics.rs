@@ -0,0 +1,71 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+lazy_static! {
+    pub static ref ROOMS: IntGauge =
+        register_int_gauge!("wigglypuff_rooms", "Number of created rooms").unwrap();
+    pub static ref SESSIONS: IntGauge = register_int_gauge!(
+        "wigglypuff_sessions",
+        "Number of connected websocket sessions"
+    )
+    .unwrap();
+    pub static ref CHANNELS: IntGauge = register_int_gauge!(
+        "wigglypuff_channels",
+        "Number of running Channel actors"
+    )
+    .unwrap();
+    pub static ref PRODUCERS: IntGauge = register_int_gauge!(
+        "wigglypuff_producers",
+        "Number of producer WebRTC pipelines"
+    )
+    .unwrap();
+    pub static ref CONSUMER_PEERS: IntGauge = register_int_gauge!(
+        "wigglypuff_consumer_peers",
+        "Number of consumer peer WebRTC pipelines"
+    )
+    .unwrap();
+    pub static ref MESSAGES_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "wigglypuff_messages_received_total",
+        "Websocket messages received by action",
+        &["action"]
+    )
+    .unwrap();
+    pub static ref FORBIDDEN_DISCONNECTS: IntCounter = register_int_counter!(
+        "wigglypuff_forbidden_disconnects_total",
+        "Websocket sessions closed after a forbidden message"
+    )
+    .unwrap();
+    pub static ref NAT_REFRESHES: IntCounter = register_int_counter!(
+        "wigglypuff_nat_refreshes_total",
+        "Successful ICE server refreshes"
+    )
+    .unwrap();
+    pub static ref NAT_REFRESH_FAILURES: IntCounter = register_int_counter!(
+        "wigglypuff_nat_refresh_failures_total",
+        "Failed ICE server refreshes"
+    )
+    .unwrap();
+    pub static ref JOIN_LATENCY: Histogram = register_histogram!(
+        "wigglypuff_join_latency_seconds",
+        "Time to verify, register and upgrade a websocket join",
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]
+    )
+    .unwrap();
+    pub static ref SDP_NEGOTIATION: Histogram = register_histogram!(
+        "wigglypuff_sdp_negotiation_seconds",
+        "Time from creating an offer to receiving the client answer",
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]
+    )
+    .unwrap();
+}
+
+pub fn encode() -> Vec<u8> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    buffer
+}
@@ -1,4 +1,9 @@
+pub mod authentication;
 pub mod message_websocket;
+pub mod metrics;
+pub mod network_transversal;
+pub mod recording;
+pub mod reporting;
 pub mod room;
 pub mod session;
 pub mod webrtc;
\ No newline at end of file
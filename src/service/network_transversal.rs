@@ -0,0 +1,376 @@
+use crate::models::{error, network_transversal};
+use crate::service::metrics;
+
+use actix::{
+    Actor, ActorFuture, Addr, AsyncContext, Context, Handler, ResponseActFuture, SpawnHandle,
+    WrapFuture,
+};
+use actix_web::client::Client;
+use hmac::{Hmac, Mac, NewMac};
+use log::info;
+use sha1::Sha1;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Clone)]
+pub enum NatProvider {
+    Static {
+        ice_servers: Vec<network_transversal::STUNTURN>,
+    },
+    Coturn {
+        urls: Vec<String>,
+        secret: String,
+        ttl: Duration,
+        user_ttl: Duration,
+    },
+    Twilio {
+        sid: String,
+        token: String,
+    },
+}
+
+impl NatProvider {
+    pub async fn fetch(&self) -> Result<network_transversal::IceServers, error::WigglypuffError> {
+        match self {
+            NatProvider::Static { ice_servers } => Ok(network_transversal::IceServers {
+                ice_servers: ice_servers.clone(),
+                ttl: None,
+            }),
+            NatProvider::Coturn {
+                urls, secret, ttl, ..
+            } => Ok(network_transversal::IceServers {
+                ice_servers: coturn_ice_servers(urls, secret, ttl, "wigglypuff"),
+                ttl: Some(*ttl),
+            }),
+            NatProvider::Twilio { sid, token } => {
+                if sid.is_empty() || token.is_empty() {
+                    info!("[NAT PROVIDER] [TWILIO] [MISSING TWILIO_SID / TWILIO_TOKEN]");
+                    return Err(error::WigglypuffError::NatProvider);
+                }
+
+                let twilio = request_twilio_token(sid, token).await?;
+
+                Ok(network_transversal::IceServers {
+                    ice_servers: twilio.ice_servers,
+                    ttl: twilio.ttl.parse::<u64>().ok().map(Duration::from_secs),
+                })
+            }
+        }
+    }
+
+    pub fn user_ice_servers(
+        &self,
+        room_name: &str,
+        uuid: &str,
+    ) -> Option<network_transversal::IceServers> {
+        match self {
+            NatProvider::Coturn {
+                urls,
+                secret,
+                user_ttl,
+                ..
+            } => Some(network_transversal::IceServers {
+                ice_servers: coturn_ice_servers(
+                    urls,
+                    secret,
+                    user_ttl,
+                    &format!("{}:{}", room_name, uuid),
+                ),
+                ttl: Some(*user_ttl),
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct Refresher {
+    nat_provider: NatProvider,
+    nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+    last_refresh: Option<SystemTime>,
+    expired_at: Option<SystemTime>,
+    retry_delay: Duration,
+    next_refresh: Option<SpawnHandle>,
+}
+
+impl Refresher {
+    pub fn new(
+        nat_provider: NatProvider,
+        nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+    ) -> Addr<Refresher> {
+        let refresher = Refresher {
+            nat_provider,
+            nats,
+            last_refresh: None,
+            expired_at: None,
+            retry_delay: MIN_RETRY_DELAY,
+            next_refresh: None,
+        };
+        refresher.start()
+    }
+
+    fn refresh(&mut self, context: &mut Context<Self>) {
+        let nat_provider = self.nat_provider.clone();
+        let refresh = async move { nat_provider.fetch().await }
+            .into_actor(self)
+            .map(|result, refresher, context| {
+                let _ = refresher.on_refreshed(result, context);
+            });
+        context.spawn(refresh);
+    }
+
+    fn on_refreshed(
+        &mut self,
+        result: Result<network_transversal::IceServers, error::WigglypuffError>,
+        context: &mut Context<Self>,
+    ) -> Result<(), error::WigglypuffError> {
+        if let Some(next_refresh) = self.next_refresh.take() {
+            context.cancel_future(next_refresh);
+        }
+
+        match result {
+            Ok(ice_servers) => {
+                let now = SystemTime::now();
+                info!(
+                    "[NAT PROVIDER] [REFRESHED] [ICE SERVERS: {}] [TTL: {:?}]",
+                    ice_servers.ice_servers.len(),
+                    ice_servers.ttl
+                );
+                *self.nats.lock().unwrap() = ice_servers.ice_servers;
+                metrics::NAT_REFRESHES.inc();
+                self.last_refresh = Some(now);
+                self.expired_at = ice_servers.ttl.map(|ttl| now + ttl);
+                self.retry_delay = MIN_RETRY_DELAY;
+
+                if let Some(ttl) = ice_servers.ttl {
+                    // refresh once 80% of the lifetime has passed so clients never
+                    // receive credentials that are about to expire
+                    let delay = cmp::max(ttl.mul_f32(0.8), MIN_RETRY_DELAY);
+                    self.next_refresh = Some(
+                        context.run_later(delay, |refresher, context| refresher.refresh(context)),
+                    );
+                }
+                Ok(())
+            }
+            Err(err) => {
+                info!(
+                    "[NAT PROVIDER] [REFRESH FAILED] [RETRY IN {:?}] [KEEPING LAST ICE SERVERS]",
+                    self.retry_delay
+                );
+                metrics::NAT_REFRESH_FAILURES.inc();
+                let delay = self.retry_delay;
+                self.retry_delay = cmp::min(self.retry_delay * 2, MAX_RETRY_DELAY);
+                self.next_refresh =
+                    Some(context.run_later(delay, |refresher, context| refresher.refresh(context)));
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Actor for Refresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        self.refresh(context);
+    }
+}
+
+impl Handler<network_transversal::Refresh> for Refresher {
+    type Result = ResponseActFuture<Self, Result<(), error::WigglypuffError>>;
+
+    fn handle(&mut self, _: network_transversal::Refresh, _: &mut Context<Self>) -> Self::Result {
+        let nat_provider = self.nat_provider.clone();
+        Box::pin(
+            async move { nat_provider.fetch().await }
+                .into_actor(self)
+                .map(|result, refresher, context| refresher.on_refreshed(result, context)),
+        )
+    }
+}
+
+impl Handler<network_transversal::GetUserIceServers> for Refresher {
+    type Result = <network_transversal::GetUserIceServers as actix::Message>::Result;
+
+    fn handle(
+        &mut self,
+        user: network_transversal::GetUserIceServers,
+        _: &mut Context<Self>,
+    ) -> network_transversal::NetworkTransversalStatus {
+        match self.nat_provider.user_ice_servers(&user.name, &user.uuid) {
+            Some(ice_servers) => {
+                let now = SystemTime::now();
+                info!(
+                    "[ROOM: {}] [UUID: {}] [ISSUING USER ICE CREDENTIALS]",
+                    user.name, user.uuid
+                );
+                network_transversal::NetworkTransversalStatus {
+                    ice_servers: ice_servers.ice_servers,
+                    last_refresh: Some(unix_timestamp(now)),
+                    expired_at: ice_servers.ttl.map(|ttl| unix_timestamp(now + ttl)),
+                }
+            }
+            None => network_transversal::NetworkTransversalStatus {
+                ice_servers: self.nats.lock().unwrap().clone(),
+                last_refresh: self.last_refresh.map(unix_timestamp),
+                expired_at: self.expired_at.map(unix_timestamp),
+            },
+        }
+    }
+}
+
+impl Handler<network_transversal::GetStatus> for Refresher {
+    type Result = <network_transversal::GetStatus as actix::Message>::Result;
+
+    fn handle(
+        &mut self,
+        _: network_transversal::GetStatus,
+        _: &mut Context<Self>,
+    ) -> network_transversal::NetworkTransversalStatus {
+        network_transversal::NetworkTransversalStatus {
+            ice_servers: self.nats.lock().unwrap().clone(),
+            last_refresh: self.last_refresh.map(unix_timestamp),
+            expired_at: self.expired_at.map(unix_timestamp),
+        }
+    }
+}
+
+fn unix_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+async fn request_twilio_token(
+    sid: &str,
+    token: &str,
+) -> Result<network_transversal::Twilio, error::WigglypuffError> {
+    let address = format!(
+        "https://api.twilio.com/2010-04-01/Accounts/{sid}/Tokens.json",
+        sid = sid
+    );
+
+    let mut response = Client::default()
+        .post(&address)
+        .basic_auth(sid, Some(token))
+        .send()
+        .await
+        .map_err(|err| {
+            info!("[NAT PROVIDER] [TWILIO] [ERROR: {}]", err);
+            error::WigglypuffError::NatProvider
+        })?;
+    if !response.status().is_success() {
+        info!("[NAT PROVIDER] [TWILIO] [STATUS: {}]", response.status());
+        return Err(error::WigglypuffError::NatProvider);
+    }
+
+    response.json().await.map_err(|err| {
+        info!("[NAT PROVIDER] [TWILIO] [INVALID RESPONSE: {}]", err);
+        error::WigglypuffError::NatProvider
+    })
+}
+
+pub fn coturn_credential(secret: &str, username: &str) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_varkey(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(username.as_bytes());
+
+    base64::encode(mac.finalize().into_bytes())
+}
+
+pub fn coturn_ice_servers(
+    urls: &[String],
+    secret: &str,
+    ttl: &Duration,
+    user: &str,
+) -> Vec<network_transversal::STUNTURN> {
+    let expired_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .checked_add(*ttl)
+        .unwrap()
+        .as_secs();
+    let username = format!("{}:{}", expired_at, user);
+    let credential = coturn_credential(secret, &username);
+
+    urls.iter()
+        .map(|url| {
+            if url.starts_with("turn:") || url.starts_with("turns:") {
+                network_transversal::STUNTURN::TURN {
+                    url: url.to_owned(),
+                    urls: vec![url.to_owned()],
+                    username: username.to_owned(),
+                    credential: credential.to_owned(),
+                }
+            } else {
+                network_transversal::STUNTURN::STUN {
+                    url: url.to_owned(),
+                    urls: vec![url.to_owned()],
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coturn_credential_is_base64_hmac_sha1() {
+        assert_eq!(
+            coturn_credential("key", "The quick brown fox jumps over the lazy dog"),
+            "3nybhbi3iqa8ino29wqQcBydtNk="
+        );
+        assert_eq!(
+            coturn_credential("north-secret", "1700000000:kelas-1:user-1"),
+            "qGBzGczT05g0cDhhhc8DhnxvG6w="
+        );
+    }
+
+    #[test]
+    fn user_ice_servers_sign_expiry_and_user() {
+        let nat_provider = NatProvider::Coturn {
+            urls: vec![
+                "stun:turn.example.com:3478".to_string(),
+                "turn:turn.example.com:3478".to_string(),
+            ],
+            secret: "north-secret".to_string(),
+            ttl: Duration::from_secs(86400),
+            user_ttl: Duration::from_secs(600),
+        };
+        let now = unix_timestamp(SystemTime::now());
+        let ice_servers = nat_provider
+            .user_ice_servers("kelas-1", "user-1")
+            .unwrap()
+            .ice_servers;
+
+        assert!(matches!(
+            &ice_servers[0],
+            network_transversal::STUNTURN::STUN { .. }
+        ));
+        match &ice_servers[1] {
+            network_transversal::STUNTURN::TURN {
+                username,
+                credential,
+                ..
+            } => {
+                let (expired_at, user) = username.split_once(':').unwrap();
+                let expired_at = expired_at.parse::<u64>().unwrap();
+                assert!(expired_at >= now + 600 && expired_at <= now + 601);
+                assert_eq!(user, "kelas-1:user-1");
+                assert_eq!(credential, &coturn_credential("north-secret", username));
+            }
+            _ => panic!("turn url without credentials"),
+        }
+    }
+
+    #[test]
+    fn only_coturn_hands_out_user_credentials() {
+        let nat_provider = NatProvider::Static {
+            ice_servers: Vec::new(),
+        };
+        assert!(nat_provider.user_ice_servers("kelas-1", "user-1").is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::models::recording;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MANIFEST: &str = "manifest.json";
+
+/// Room names and uuids come from clients, so only a safe subset of characters reaches the
+/// filesystem.
+pub fn file_name(name: &str) -> String {
+    name.chars()
+        .map(
+            |character| match character.is_ascii_alphanumeric() || character == '-' {
+                true => character,
+                false => '_',
+            },
+        )
+        .collect()
+}
+
+pub fn room_directory(base: &Path, room_name: &str) -> PathBuf {
+    base.join(file_name(room_name))
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+pub fn write_manifest(directory: &Path, manifest: &recording::Manifest) -> io::Result<()> {
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+    fs::write(directory.join(MANIFEST), manifest)
+}
+
+fn read_manifest(directory: &Path) -> Option<recording::Manifest> {
+    let manifest = fs::read(directory.join(MANIFEST)).ok()?;
+    serde_json::from_slice(&manifest).ok()
+}
+
+pub fn finished_recordings(base: &Path, room_name: &str) -> Vec<recording::Manifest> {
+    let entries = match fs::read_dir(room_directory(base, room_name)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut manifests: Vec<recording::Manifest> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| read_manifest(&entry.path()))
+        .filter(|manifest| manifest.room == room_name && manifest.stopped_at.is_some())
+        .collect();
+    manifests.sort_by_key(|manifest| manifest.started_at);
+    manifests
+}
+
+/// Resolves a file of a finished recording, only files listed in its manifest are served.
+pub fn recording_file(base: &Path, room_name: &str, id: &str, file: &str) -> Option<PathBuf> {
+    if id.is_empty() || id.contains(|character: char| !character.is_ascii_digit()) {
+        return None;
+    }
+    let directory = room_directory(base, room_name).join(id);
+    let manifest = read_manifest(&directory)?;
+
+    let listed = manifest.mixed.as_deref() == Some(file)
+        || manifest.tracks.iter().any(|track| track.file == file);
+    match manifest.room == room_name && manifest.stopped_at.is_some() && listed {
+        true => Some(directory.join(file)),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_directory(test: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!(
+            "wigglypuff-recording-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&base);
+        base
+    }
+
+    fn save_recording(base: &Path, room_name: &str, id: &str, stopped: bool) {
+        let directory = room_directory(base, room_name).join(id);
+        fs::create_dir_all(&directory).unwrap();
+        let manifest = recording::Manifest {
+            room: room_name.to_string(),
+            id: id.to_string(),
+            started_at: 1000,
+            stopped_at: match stopped {
+                true => Some(2000),
+                false => None,
+            },
+            mixed: Some("mixed.ogg".to_string()),
+            tracks: vec![recording::ManifestTrack {
+                uuid: "user-1".to_string(),
+                file: "1_user-1.ogg".to_string(),
+                start_offset_ms: 0,
+                end_offset_ms: Some(1000),
+            }],
+        };
+        write_manifest(&directory, &manifest).unwrap();
+    }
+
+    #[test]
+    fn file_name_replaces_unsafe_characters() {
+        assert_eq!(file_name("room-1"), "room-1");
+        assert_eq!(file_name(".."), "__");
+        assert_eq!(file_name("../etc/passwd"), "___etc_passwd");
+        assert_eq!(file_name("a\\b c"), "a_b_c");
+    }
+
+    #[test]
+    fn room_directory_stays_in_base() {
+        let base = Path::new("/recordings");
+        assert_eq!(room_directory(base, ".."), base.join("__"));
+        assert_eq!(room_directory(base, "../room"), base.join("___room"));
+    }
+
+    #[test]
+    fn recording_file_serves_listed_files() {
+        let base = base_directory("listed");
+        save_recording(&base, "room", "1000", true);
+
+        let directory = room_directory(&base, "room").join("1000");
+        assert_eq!(
+            recording_file(&base, "room", "1000", "mixed.ogg"),
+            Some(directory.join("mixed.ogg"))
+        );
+        assert_eq!(
+            recording_file(&base, "room", "1000", "1_user-1.ogg"),
+            Some(directory.join("1_user-1.ogg"))
+        );
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn recording_file_rejects_unlisted_files() {
+        let base = base_directory("unlisted");
+        save_recording(&base, "room", "1000", true);
+
+        assert_eq!(recording_file(&base, "room", "1000", MANIFEST), None);
+        assert_eq!(recording_file(&base, "room", "1000", "2_user-2.ogg"), None);
+        assert_eq!(recording_file(&base, "room", "1000", ".."), None);
+        assert_eq!(
+            recording_file(&base, "room", "1000", "../1000/mixed.ogg"),
+            None
+        );
+        assert_eq!(recording_file(&base, "room", "1000", "/etc/passwd"), None);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn recording_file_rejects_non_numeric_ids() {
+        let base = base_directory("ids");
+        save_recording(&base, "room", "1000", true);
+
+        assert_eq!(recording_file(&base, "room", "", "mixed.ogg"), None);
+        assert_eq!(recording_file(&base, "room", "..", "mixed.ogg"), None);
+        assert_eq!(recording_file(&base, "room", "1000/..", "mixed.ogg"), None);
+        assert_eq!(
+            recording_file(&base, "room", "../room/1000", "mixed.ogg"),
+            None
+        );
+        assert_eq!(recording_file(&base, "room", "abc", "mixed.ogg"), None);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn recording_file_rejects_other_rooms() {
+        let base = base_directory("rooms");
+        save_recording(&base, "room", "1000", true);
+
+        assert_eq!(recording_file(&base, "other", "1000", "mixed.ogg"), None);
+        assert_eq!(recording_file(&base, "../room", "1000", "mixed.ogg"), None);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn unfinished_recordings_are_hidden() {
+        let base = base_directory("unfinished");
+        save_recording(&base, "room", "1000", false);
+        save_recording(&base, "room", "3000", true);
+
+        assert_eq!(recording_file(&base, "room", "1000", "mixed.ogg"), None);
+        let finished: Vec<String> = finished_recordings(&base, "room")
+            .into_iter()
+            .map(|manifest| manifest.id)
+            .collect();
+        assert_eq!(finished, vec!["3000".to_string()]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+use crate::models::webrtc;
+
+use sentry::{Hub, Level, ScopeGuard};
+
+pub fn role_name(role: &webrtc::Role) -> &'static str {
+    match role {
+        webrtc::Role::Producer => "producer",
+        webrtc::Role::Consumer => "consumer",
+    }
+}
+
+// tags every event (including panics) captured until the guard is dropped
+pub fn push_user_scope(room_name: &str, uuid: &str, role: &str) -> ScopeGuard {
+    let hub = Hub::current();
+    let guard = hub.push_scope();
+    hub.configure_scope(|scope| {
+        scope.set_tag("room", room_name);
+        scope.set_tag("uuid", uuid);
+        scope.set_tag("role", role);
+    });
+    guard
+}
+
+// owner is a producer uuid or a `src:X_sink:Y` peer key, as resolved by Channel
+pub fn capture_bus_message(
+    room_name: &str,
+    element: &str,
+    owner: Option<&str>,
+    message: &str,
+    level: Level,
+) {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("room", room_name);
+            scope.set_tag("element", element);
+            if let Some(owner) = owner {
+                scope.set_tag("owner", owner);
+                if let Some(uuid) = owner.rsplit("_sink:").next() {
+                    scope.set_tag("uuid", uuid);
+                }
+            }
+        },
+        || sentry::capture_message(message, level),
+    );
+}
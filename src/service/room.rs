@@ -1,20 +1,58 @@
-use crate::models::{message_websocket, room, webrtc};
-use actix::{Actor, Addr, Context, Handler, Recipient};
+use crate::constants;
+use crate::models::{error, message_websocket, room, supervisor, webrtc};
+use crate::service::metrics;
+use crate::service::webrtc::supervisor as supervisor_service;
+use actix::{Actor, Addr, AsyncContext, Context, Handler, Recipient, SpawnHandle};
 use log::info;
 use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_SUSPENDED_MESSAGES: usize = 256;
+
+struct SuspendedSession {
+    room_name: String,
+    session_id: String,
+    messages: Vec<String>,
+    resuming: bool,
+    leave_handle: SpawnHandle,
+    webrtc_address: Addr<supervisor_service::Supervisor>,
+}
 
 pub struct Room {
     sessions: BTreeMap<String, Recipient<room::Message>>,
+    session_ids: BTreeMap<String, String>,
+    suspended: BTreeMap<String, SuspendedSession>,
     rooms: BTreeMap<String, HashSet<String>>,
     masters: BTreeMap<String, String>,
+    // room name -> muted uuid -> muted by the master
+    muted: BTreeMap<String, BTreeMap<String, bool>>,
+    mute_all_by: BTreeMap<String, String>,
+    lessons: BTreeMap<String, room::Lesson>,
+    heartbeats: BTreeMap<String, room::HeartbeatStatus>,
+    grace_period: Duration,
+    max_rooms: Option<usize>,
+    max_participants: Option<usize>,
 }
 
 impl Room {
-    pub fn new() -> Addr<Room> {
+    pub fn new(
+        grace_period: Duration,
+        max_rooms: Option<usize>,
+        max_participants: Option<usize>,
+    ) -> Addr<Room> {
         let room = Room {
             sessions: BTreeMap::new(),
+            session_ids: BTreeMap::new(),
+            suspended: BTreeMap::new(),
             rooms: BTreeMap::new(),
             masters: BTreeMap::new(),
+            muted: BTreeMap::new(),
+            mute_all_by: BTreeMap::new(),
+            lessons: BTreeMap::new(),
+            heartbeats: BTreeMap::new(),
+            grace_period,
+            max_rooms,
+            max_participants,
         };
         room.start()
     }
@@ -27,25 +65,307 @@ impl Actor for Room {
 impl Handler<room::Connect> for Room {
     type Result = ();
 
-    fn handle(&mut self, connect: room::Connect, _: &mut Context<Self>) {
+    fn handle(&mut self, connect: room::Connect, context: &mut Context<Self>) {
+        let missed_messages = match self.suspended.remove(&connect.uuid) {
+            Some(suspended) => {
+                context.cancel_future(suspended.leave_handle);
+                if suspended.room_name == connect.room_name
+                    && connect.resume_token.as_ref() == Some(&suspended.session_id)
+                {
+                    info!(
+                        "[ROOM: {}] [UUID: {}] [SESSION RESUMED] [MISSED MESSAGES: {}]",
+                        connect.room_name,
+                        connect.uuid,
+                        suspended.messages.len()
+                    );
+                    suspended.messages
+                } else {
+                    // a fresh join into the same room replaces the old pipeline in Channel by itself
+                    let release_pipeline = suspended.room_name != connect.room_name;
+                    self.leave(
+                        &connect.uuid,
+                        &suspended.room_name,
+                        &suspended.webrtc_address,
+                        release_pipeline,
+                    );
+                    Vec::new()
+                }
+            }
+            None => Vec::new(),
+        };
+
         self.sessions
             .insert(connect.uuid.clone(), connect.session_address);
+        self.session_ids
+            .insert(connect.uuid.clone(), connect.session_id.clone());
 
         self.rooms
             .entry(connect.room_name.clone())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .insert(connect.uuid.clone());
+
+        let resume_token =
+            serde_json::to_string(&message_websocket::MessageSocketType::ResumeToken {
+                token: connect.session_id.clone(),
+            })
+            .unwrap();
+        // sent past send_user, which logs message bodies and the token takes over the session
+        info!(
+            "[ROOM: {}] [SEND USER] [TO UUID: {}] [RESUME TOKEN]",
+            connect.room_name, connect.uuid
+        );
+        if let Some(session_address) = self.sessions.get(&connect.uuid) {
+            let _ = session_address.do_send(room::Message(resume_token));
+        }
+
+        if self.masters.contains_key(&connect.room_name) {
+            let room_state =
+                serde_json::to_string(&message_websocket::MessageSocketType::RoomState(
+                    self.room_state(&connect.room_name),
+                ))
+                .unwrap();
+            self.send_user(&connect.uuid, &connect.room_name, &room_state);
+        }
+
+        for message in missed_messages {
+            self.send_user(&connect.uuid, &connect.room_name, &message);
+        }
+    }
+}
+
+impl Handler<room::Disconnect> for Room {
+    type Result = ();
+
+    fn handle(&mut self, disconnect: room::Disconnect, context: &mut Context<Self>) {
+        if self.session_ids.get(&disconnect.uuid) != Some(&disconnect.session_id) {
+            return;
+        }
+        if disconnect.leaving {
+            self.sessions.remove(&disconnect.uuid);
+            self.session_ids.remove(&disconnect.uuid);
+            if let Some(suspended) = self.suspended.remove(&disconnect.uuid) {
+                context.cancel_future(suspended.leave_handle);
+            }
+            self.leave(
+                &disconnect.uuid,
+                &disconnect.room_name,
+                &disconnect.webrtc_address,
+                true,
+            );
+            return;
+        }
+        info!(
+            "[ROOM: {}] [UUID: {}] [SESSION SUSPENDED] [LEAVING IN {:?}]",
+            disconnect.room_name, disconnect.uuid, self.grace_period
+        );
+        self.sessions.remove(&disconnect.uuid);
+
+        let uuid = disconnect.uuid.clone();
+        let session_id = disconnect.session_id.clone();
+        let leave_handle = context.run_later(self.grace_period, move |room, _| {
+            room.expire_suspended(&uuid, &session_id);
+        });
+
+        match self.suspended.get_mut(&disconnect.uuid) {
+            Some(suspended) => {
+                context.cancel_future(suspended.leave_handle);
+                suspended.leave_handle = leave_handle;
+                suspended.resuming = false;
+            }
+            None => {
+                self.suspended.insert(
+                    disconnect.uuid,
+                    SuspendedSession {
+                        room_name: disconnect.room_name,
+                        session_id: disconnect.session_id,
+                        messages: Vec::new(),
+                        resuming: false,
+                        leave_handle,
+                        webrtc_address: disconnect.webrtc_address,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl Handler<room::Resume> for Room {
+    type Result = bool;
+
+    fn handle(&mut self, resume: room::Resume, context: &mut Context<Self>) -> bool {
+        match self.suspended.get_mut(&resume.uuid) {
+            Some(suspended)
+                if suspended.room_name == resume.room_name
+                    && suspended.session_id == resume.resume_token =>
+            {
+                context.cancel_future(suspended.leave_handle);
+                suspended.resuming = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Handler<room::Heartbeat> for Room {
+    type Result = ();
+
+    fn handle(&mut self, heartbeat: room::Heartbeat, _: &mut Context<Self>) {
+        if !self.sessions.contains_key(&heartbeat.uuid) {
+            return;
+        }
+        info!(
+            "[ROOM: {}] [UUID: {}] [HEARTBEAT RTT: {:?}]",
+            heartbeat.room_name, heartbeat.uuid, heartbeat.rtt
+        );
+        let last_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.heartbeats.insert(
+            heartbeat.uuid,
+            room::HeartbeatStatus {
+                rtt_ms: heartbeat.rtt.as_millis() as u64,
+                last_seen,
+            },
+        );
+    }
+}
+
+impl Handler<room::UpdateLesson> for Room {
+    type Result = ();
+
+    fn handle(&mut self, update: room::UpdateLesson, _: &mut Context<Self>) {
+        let lesson = self.lessons.entry(update.room_name.clone()).or_default();
+        match update.data {
+            message_websocket::MessageSocketType::MoveSura { id_quran } => {
+                lesson.id_quran = Some(id_quran);
+                lesson.aya = None;
+            }
+            message_websocket::MessageSocketType::ClickAya { aya } => {
+                lesson.aya = Some(aya);
+            }
+            _ => {}
+        }
+
+        let message = serde_json::to_string(&update.data).unwrap();
+        self.broadcast(&update.uuid, &update.room_name, &message);
+    }
+}
+
+impl Handler<room::UpdateMute> for Room {
+    type Result = ();
+
+    fn handle(&mut self, mute: room::UpdateMute, _: &mut Context<Self>) {
+        info!(
+            "[ROOM: {}] [FROM UUID: {}] [TO UUID: {:?}] [MUTE: {}]",
+            mute.room_name, mute.from_uuid, mute.uuid, mute.muted
+        );
+        let muted = self.muted.entry(mute.room_name.clone()).or_default();
+
+        let uuids: Vec<String> = match &mute.uuid {
+            Some(uuid) => vec![uuid.clone()],
+            None => {
+                let mut uuids: Vec<String> = self
+                    .rooms
+                    .get(&mute.room_name)
+                    .into_iter()
+                    .flatten()
+                    .chain(muted.keys())
+                    .filter(|uuid| **uuid != mute.from_uuid)
+                    .cloned()
+                    .collect();
+                uuids.sort();
+                uuids.dedup();
+                uuids
+            }
+        };
+
+        if !mute.muted
+            && !mute.is_master
+            && uuids
+                .iter()
+                .any(|uuid| muted.get(uuid).cloned().unwrap_or(false))
+        {
+            self.send_user(
+                &mute.from_uuid,
+                &mute.room_name,
+                constants::MESSAGE_MUTED_BY_MASTER,
+            );
+            return;
+        }
+
+        for uuid in &uuids {
+            if mute.muted {
+                let by_master = muted.get(uuid).cloned().unwrap_or(false) || mute.is_master;
+                muted.insert(uuid.clone(), by_master);
+            } else {
+                muted.remove(uuid);
+            }
+        }
+        if muted.is_empty() {
+            self.muted.remove(&mute.room_name);
+        }
+        if mute.uuid.is_none() {
+            match mute.muted {
+                true => self
+                    .mute_all_by
+                    .insert(mute.room_name.clone(), mute.from_uuid.clone()),
+                false => self.mute_all_by.remove(&mute.room_name),
+            };
+        }
+
+        mute.webrtc_address.do_send(webrtc::Mute {
+            room_name: mute.room_name.clone(),
+            uuids,
+            muted: mute.muted,
+        });
+        self.broadcast(&mute.from_uuid, &mute.room_name, &mute.message);
+    }
+}
+
+impl Handler<room::JoinMuted> for Room {
+    type Result = bool;
+
+    fn handle(&mut self, join: room::JoinMuted, _: &mut Context<Self>) -> bool {
+        let mute_all = match self.mute_all_by.get(&join.room_name) {
+            Some(master_uuid) => *master_uuid != join.uuid,
+            None => false,
+        };
+        let already_muted = self
+            .muted
+            .get(&join.room_name)
+            .is_some_and(|muted| muted.contains_key(&join.uuid));
+
+        if mute_all && !already_muted {
+            self.muted
+                .entry(join.room_name.clone())
+                .or_default()
+                .insert(join.uuid.clone(), true);
+            let message = serde_json::to_string(&message_websocket::MessageSocketType::MuteUser {
+                uuid: join.uuid.clone(),
+            })
+            .unwrap();
+            self.broadcast("wigglypuff", &join.room_name, &message);
+        }
+
+        mute_all || already_muted
     }
 }
 
 impl Handler<room::KickUser> for Room {
     type Result = ();
 
-    fn handle(&mut self, user: room::KickUser, _: &mut Context<Self>) {
+    fn handle(&mut self, user: room::KickUser, context: &mut Context<Self>) {
         self.sessions.remove(&user.uuid);
+        self.session_ids.remove(&user.uuid);
+        self.heartbeats.remove(&user.uuid);
+        if let Some(suspended) = self.suspended.remove(&user.uuid) {
+            context.cancel_future(suspended.leave_handle);
+        }
         self.rooms
             .entry(user.room_name.clone())
-            .or_insert_with(HashSet::new)
+            .or_default()
             .remove(&user.uuid);
         self.masters.remove(&user.uuid);
         let status = serde_json::to_string(&message_websocket::UserStatus {
@@ -66,6 +386,16 @@ impl Handler<room::SendUser> for Room {
     }
 }
 
+impl Handler<room::SendMaster> for Room {
+    type Result = ();
+
+    fn handle(&mut self, master: room::SendMaster, _: &mut Context<Self>) {
+        if let Some(master_uuid) = self.masters.get(&master.room_name).cloned() {
+            self.send_user(&master_uuid, &master.room_name, &master.message);
+        }
+    }
+}
+
 impl Handler<room::Broadcast> for Room {
     type Result = ();
 
@@ -75,21 +405,39 @@ impl Handler<room::Broadcast> for Room {
 }
 
 impl Handler<room::CreateRoom> for Room {
-    type Result = bool;
+    type Result = Result<(), error::WigglypuffError>;
 
     fn handle(&mut self, create_room: room::CreateRoom, _: &mut Context<Self>) -> Self::Result {
-        match !self.masters.contains_key(&create_room.name)
-            && !self.rooms.contains_key(&create_room.name)
+        if self.masters.contains_key(&create_room.name)
+            || self.rooms.contains_key(&create_room.name)
         {
-            true => {
-                self.masters
-                    .insert(create_room.name.clone(), create_room.master_uuid.clone());
-                self.rooms
-                    .insert(create_room.name.to_owned(), HashSet::new());
+            return Err(error::WigglypuffError::RoomAlreadyExist);
+        }
+        if let Some(max_rooms) = self.max_rooms {
+            if self.masters.len() >= max_rooms {
+                return Err(error::WigglypuffError::RoomLimitReached);
+            }
+        }
 
-                true
+        self.masters
+            .insert(create_room.name.clone(), create_room.master_uuid.clone());
+        self.rooms
+            .insert(create_room.name.to_owned(), HashSet::new());
+        metrics::ROOMS.set(self.masters.len() as i64);
+
+        Ok(())
+    }
+}
+
+impl Handler<room::CheckCapacity> for Room {
+    type Result = bool;
+
+    fn handle(&mut self, capacity: room::CheckCapacity, _: &mut Context<Self>) -> bool {
+        match (self.max_participants, self.rooms.get(&capacity.room_name)) {
+            (Some(max_participants), Some(sessions)) => {
+                sessions.contains(&capacity.uuid) || sessions.len() < max_participants
             }
-            false => false,
+            _ => true,
         }
     }
 }
@@ -99,8 +447,8 @@ impl Handler<room::GetMaster> for Room {
 
     fn handle(&mut self, master_request: room::GetMaster, _: &mut Context<Self>) -> String {
         match self.masters.get(&master_request.room_name) {
-            Some(master_uuid) => return master_uuid.to_owned(),
-            None => return "NAN".to_string(),
+            Some(master_uuid) => master_uuid.to_owned(),
+            None => "NAN".to_string(),
         }
     }
 }
@@ -109,45 +457,54 @@ impl Handler<room::GetRooms> for Room {
     type Result = <room::GetRooms as actix::Message>::Result;
 
     fn handle(&mut self, _: room::GetRooms, _: &mut Context<Self>) -> room::Rooms {
-        let rooms: Vec<String> = self
-            .rooms
-            .clone()
-            .into_iter()
-            .map(|(room_name, _)| room_name)
-            .collect();
+        let rooms: Vec<String> = self.rooms.clone().into_keys().collect();
 
         room::Rooms(rooms)
     }
 }
 
-impl Handler<room::GetUsers> for Room {
-    type Result = <room::GetUsers as actix::Message>::Result;
+impl Handler<room::GetRoomState> for Room {
+    type Result = <room::GetRoomState as actix::Message>::Result;
 
-    fn handle(&mut self, room: room::GetUsers, _: &mut Context<Self>) -> room::Users {
-        match self.masters.get(&room.name) {
-            Some(_) => {
-                let users = self
-                    .rooms
-                    .get(&room.name)
-                    .unwrap()
-                    .iter()
-                    .map(String::from)
-                    .collect();
-                return room::Users(users);
+    fn handle(&mut self, room: room::GetRoomState, _: &mut Context<Self>) -> room::RoomState {
+        self.room_state(&room.name)
+    }
+}
+
+impl Handler<room::GetHeartbeats> for Room {
+    type Result = <room::GetHeartbeats as actix::Message>::Result;
+
+    fn handle(&mut self, room: room::GetHeartbeats, _: &mut Context<Self>) -> room::Heartbeats {
+        let mut heartbeats = BTreeMap::new();
+        if let Some(sessions) = self.rooms.get(&room.name) {
+            for session in sessions {
+                if let Some(heartbeat) = self.heartbeats.get(session) {
+                    heartbeats.insert(session.to_owned(), heartbeat.clone());
+                }
             }
-            None => return room::Users(Vec::new()),
-        };
+        }
+
+        room::Heartbeats(heartbeats)
     }
 }
 
 impl Handler<room::DeleteRoom> for Room {
     type Result = <room::DeleteRoom as actix::Message>::Result;
 
-    fn handle(&mut self, delete_room: room::DeleteRoom, _: &mut Context<Self>) {
+    fn handle(&mut self, delete_room: room::DeleteRoom, context: &mut Context<Self>) {
         self.masters.remove(&delete_room.name);
+        metrics::ROOMS.set(self.masters.len() as i64);
+        self.muted.remove(&delete_room.name);
+        self.mute_all_by.remove(&delete_room.name);
+        self.lessons.remove(&delete_room.name);
         if let Some(sessions) = self.rooms.get(&delete_room.name) {
             for session in sessions {
                 self.sessions.remove(session);
+                self.session_ids.remove(session);
+                self.heartbeats.remove(session);
+                if let Some(suspended) = self.suspended.remove(session) {
+                    context.cancel_future(suspended.leave_handle);
+                }
             }
         }
         self.rooms.remove(&delete_room.name);
@@ -165,11 +522,7 @@ impl Handler<room::DeleteRoom> for Room {
         })
         .unwrap();
 
-        self.broadcast(
-            &"wigglypuff".to_string(),
-            &delete_room.name,
-            &message_remove_user,
-        );
+        self.broadcast("wigglypuff", &delete_room.name, &message_remove_user);
     }
 }
 
@@ -178,7 +531,7 @@ impl Handler<webrtc::WigglypuffWebRTC> for Room {
 
     fn handle(&mut self, mut webrtc: webrtc::WigglypuffWebRTC, _: &mut Context<Self>) {
         match webrtc.data {
-            message_websocket::MessageSocketType::WebRTCConnectionState {} => {
+            message_websocket::MessageSocketType::WebRTCConnectionState => {
                 let message_new_user = serde_json::to_string(&message_websocket::UserStatus {
                     action: "NewUser",
                     uuid: &webrtc.uuid,
@@ -188,8 +541,8 @@ impl Handler<webrtc::WigglypuffWebRTC> for Room {
             }
             _ => {
                 let (uuid_src, uuid_sink) = match webrtc.role {
-                    webrtc::Role::Producer {} => (webrtc.uuid.clone(), webrtc.uuid.clone()),
-                    webrtc::Role::Consumer {} => {
+                    webrtc::Role::Producer => (webrtc.uuid.clone(), webrtc.uuid.clone()),
+                    webrtc::Role::Consumer => {
                         let result: Vec<&str> = webrtc.uuid.split("_sink:").collect();
                         (result[0][4..].to_string(), result[1].to_string())
                     }
@@ -203,7 +556,71 @@ impl Handler<webrtc::WigglypuffWebRTC> for Room {
 }
 
 impl Room {
-    fn broadcast(&self, from_uuid: &str, room_name: &str, message: &str) {
+    fn expire_suspended(&mut self, uuid: &str, session_id: &str) {
+        let is_expired = match self.suspended.get(uuid) {
+            Some(suspended) => suspended.session_id == session_id && !suspended.resuming,
+            None => false,
+        };
+
+        if is_expired {
+            let suspended = self.suspended.remove(uuid).unwrap();
+            self.session_ids.remove(uuid);
+            self.leave(uuid, &suspended.room_name, &suspended.webrtc_address, true);
+        }
+    }
+
+    fn leave(
+        &mut self,
+        uuid: &str,
+        room_name: &str,
+        webrtc_address: &Addr<supervisor_service::Supervisor>,
+        release_pipeline: bool,
+    ) {
+        info!("[ROOM: {}] [UUID: {}] [SESSION LEFT]", room_name, uuid);
+        if let Some(sessions) = self.rooms.get_mut(room_name) {
+            sessions.remove(uuid);
+        }
+        self.heartbeats.remove(uuid);
+
+        let user_disconnected_json_message =
+            serde_json::to_string(&message_websocket::UserStatus {
+                action: "UserLeave",
+                uuid,
+            })
+            .unwrap();
+        self.broadcast(uuid, room_name, &user_disconnected_json_message);
+
+        if release_pipeline {
+            webrtc_address.do_send(supervisor::DeleteUser {
+                uuid: uuid.to_owned(),
+                room_name: room_name.to_owned(),
+            });
+        }
+    }
+
+    fn room_state(&self, room_name: &str) -> room::RoomState {
+        let lesson = self.lessons.get(room_name).cloned().unwrap_or_default();
+        let mut muted: Vec<String> = match self.muted.get(room_name) {
+            Some(muted) => muted.keys().map(String::from).collect(),
+            None => Vec::new(),
+        };
+        muted.sort();
+        let mut participants: Vec<String> = match self.rooms.get(room_name) {
+            Some(sessions) => sessions.iter().map(String::from).collect(),
+            None => Vec::new(),
+        };
+        participants.sort();
+
+        room::RoomState {
+            master_uuid: self.masters.get(room_name).cloned().unwrap_or_default(),
+            id_quran: lesson.id_quran,
+            aya: lesson.aya,
+            muted,
+            participants,
+        }
+    }
+
+    fn broadcast(&mut self, from_uuid: &str, room_name: &str, message: &str) {
         info!(
             "[ROOM: {}] [FROM UUID: {}] [BROADCAST]",
             room_name, from_uuid
@@ -213,12 +630,16 @@ impl Room {
                 if *session != from_uuid {
                     if let Some(room_address) = self.sessions.get(session) {
                         let _ = room_address.do_send(room::Message(message.to_string()));
+                    } else if let Some(suspended) = self.suspended.get_mut(session) {
+                        if suspended.messages.len() < MAX_SUSPENDED_MESSAGES {
+                            suspended.messages.push(message.to_string());
+                        }
                     }
                 }
             }
         }
     }
-    fn send_user(&self, to_uuid: &str, room_name: &str, message: &str) {
+    fn send_user(&mut self, to_uuid: &str, room_name: &str, message: &str) {
         info!(
             "[ROOM: {}] [SEND USER] [TO UUID: {}] [MESSAGE: {}]",
             room_name, to_uuid, message
@@ -228,6 +649,10 @@ impl Room {
                 if *session == to_uuid {
                     if let Some(room_address) = self.sessions.get(session) {
                         let _ = room_address.do_send(room::Message(message.to_string()));
+                    } else if let Some(suspended) = self.suspended.get_mut(session) {
+                        if suspended.messages.len() < MAX_SUSPENDED_MESSAGES {
+                            suspended.messages.push(message.to_string());
+                        }
                     }
                 }
             }
@@ -1,49 +1,59 @@
 use crate::{
     constants, models,
-    models::message_websocket::{MessageSocketType, UserStatus},
+    models::message_websocket::MessageSocketType,
     service,
-    service::{message_websocket, webrtc},
+    service::{message_websocket, metrics, webrtc},
 };
 
 use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, Running, StreamHandler};
 use actix_web_actors::ws;
+use log::info;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
 
 pub struct Session {
     pub room_name: String,
     pub uuid: String,
+    pub session_id: String,
+    pub resume_token: Option<String>,
     pub room_address: Addr<service::room::Room>,
     pub master_uuid: String,
     pub webrtc_address: Addr<webrtc::supervisor::Supervisor>,
+    pub heartbeat: Heartbeat,
+    pub last_seen: Instant,
+    pub ping_sent: Option<Instant>,
+    pub leaving: bool,
 }
 
 impl Actor for Session {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, context: &mut Self::Context) {
+        metrics::SESSIONS.inc();
         let session_address = context.address();
         self.room_address.do_send(models::room::Connect {
             room_name: self.room_name.to_owned(),
             uuid: self.uuid.to_owned(),
+            session_id: self.session_id.to_owned(),
+            resume_token: self.resume_token.to_owned(),
             session_address: session_address.recipient(),
         });
+        self.start_heartbeat(context);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        let user_disconnected_json_message = serde_json::to_string(&UserStatus {
-            action: "UserLeave",
-            uuid: &self.uuid,
-        })
-        .unwrap();
-
-        self.webrtc_address.do_send(models::supervisor::DeleteUser {
-            uuid: self.uuid.clone(),
-            room_name: self.room_name.clone(),
-        });
-
-        self.room_address.do_send(models::room::Broadcast {
-            uuid: self.uuid.to_owned(),
+        metrics::SESSIONS.dec();
+        self.room_address.do_send(models::room::Disconnect {
             room_name: self.room_name.to_owned(),
-            message: user_disconnected_json_message,
+            uuid: self.uuid.to_owned(),
+            session_id: self.session_id.to_owned(),
+            webrtc_address: self.webrtc_address.clone(),
+            leaving: self.leaving,
         });
 
         Running::Stop
@@ -64,38 +74,58 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Session {
         message: Result<ws::Message, ws::ProtocolError>,
         context: &mut Self::Context,
     ) {
+        self.last_seen = Instant::now();
         match message {
             Ok(ws::Message::Ping(message)) => context.pong(&message),
+            Ok(ws::Message::Pong(_)) => {
+                if let Some(ping_sent) = self.ping_sent.take() {
+                    self.room_address.do_send(models::room::Heartbeat {
+                        room_name: self.room_name.to_owned(),
+                        uuid: self.uuid.to_owned(),
+                        rtt: ping_sent.elapsed(),
+                    });
+                }
+            }
+            Ok(ws::Message::Close(reason)) => {
+                self.leaving = true;
+                context.close(reason);
+                context.stop();
+            }
             Ok(ws::Message::Text(text)) => {
-                let message_value = serde_json::from_str(text.as_str());
+                let message_value = serde_json::from_str::<MessageSocketType>(text.as_str());
+                if let Ok(message) = &message_value {
+                    metrics::MESSAGES_RECEIVED
+                        .with_label_values(&[message.action()])
+                        .inc();
+                }
                 match message_value {
                     Ok(message) => match message {
-                        MessageSocketType::MuteUser { ref uuid, .. } => {
-                            if self.uuid == self.master_uuid || &self.uuid == uuid {
-                                message_websocket::broadcast_to_room(self, &message);
-                            } else {
-                                context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                                context.stop();
-                            }
+                        MessageSocketType::MuteUser { ref uuid, .. }
+                        | MessageSocketType::UnmuteUser { ref uuid, .. }
+                            if self.uuid == self.master_uuid || &self.uuid == uuid =>
+                        {
+                            message_websocket::send_mute_to_room(self, &message);
                         }
                         MessageSocketType::MuteAllUser { .. }
-                        | MessageSocketType::AnswerCorrection { .. }
-                        | MessageSocketType::MoveSura { .. }
-                        | MessageSocketType::ClickAya { .. } => {
-                            if self.uuid == self.master_uuid {
-                                message_websocket::broadcast_to_room(self, &message);
-                            } else {
-                                context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                                context.stop();
-                            }
+                        | MessageSocketType::UnmuteAllUser { .. }
+                            if self.uuid == self.master_uuid =>
+                        {
+                            message_websocket::send_mute_to_room(self, &message);
+                        }
+                        MessageSocketType::AnswerCorrection { .. }
+                            if self.uuid == self.master_uuid =>
+                        {
+                            message_websocket::broadcast_to_room(self, &message);
                         }
-                        MessageSocketType::OfferCorrection { ref uuid, .. } => {
-                            if &self.uuid != uuid {
-                                message_websocket::send_to_master(self, &message)
-                            } else {
-                                context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                                context.stop();
-                            }
+                        MessageSocketType::MoveSura { .. } | MessageSocketType::ClickAya { .. }
+                            if self.uuid == self.master_uuid =>
+                        {
+                            message_websocket::update_lesson(self, &message);
+                        }
+                        MessageSocketType::OfferCorrection { ref uuid, .. }
+                            if &self.uuid != uuid =>
+                        {
+                            message_websocket::send_to_master(self, &message)
                         }
                         MessageSocketType::ICECandidate { .. } => {
                             message_websocket::send_to_client_webrtc(self, &message);
@@ -106,21 +136,55 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Session {
                         MessageSocketType::RequestPair { .. } => {
                             message_websocket::send_to_client_webrtc(self, &message);
                         }
+                        MessageSocketType::RestartICE { .. } => {
+                            message_websocket::send_to_client_webrtc(self, &message);
+                        }
+                        MessageSocketType::StartRecording { .. }
+                        | MessageSocketType::StopRecording { .. }
+                            if self.uuid == self.master_uuid =>
+                        {
+                            message_websocket::send_recording_to_channel(self, &message);
+                        }
                         _ => {
-                            context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                            context.stop();
+                            self.forbidden(context);
                         }
                     },
                     _ => {
-                        context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                        context.stop();
+                        self.forbidden(context);
                     }
                 }
             }
             _ => {
-                context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
-                context.stop()
+                self.forbidden(context);
             }
         }
     }
 }
+
+impl Session {
+    fn forbidden(&mut self, context: &mut ws::WebsocketContext<Self>) {
+        metrics::FORBIDDEN_DISCONNECTS.inc();
+        self.leaving = true;
+        context.text(constants::MESSAGE_FORBIDDEN_AUTHZ.to_string());
+        context.stop();
+    }
+
+    fn start_heartbeat(&self, context: &mut ws::WebsocketContext<Self>) {
+        context.run_interval(self.heartbeat.interval, |session, context| {
+            if session.last_seen.elapsed() > session.heartbeat.timeout {
+                info!(
+                    "[ROOM: {}] [UUID: {}] [HEARTBEAT TIMEOUT]",
+                    session.room_name, session.uuid
+                );
+                // idle sessions give their room slot back instead of being suspended
+                session.leaving = true;
+                context.close(Some(ws::CloseCode::Away.into()));
+                context.stop();
+                return;
+            }
+
+            session.ping_sent = Some(Instant::now());
+            context.ping(b"");
+        });
+    }
+}
@@ -1,72 +1,375 @@
+use crate::models::error;
 use crate::models::message_websocket;
 use crate::models::network_transversal;
+use crate::models::recording;
 use crate::models::room;
 use crate::models::supervisor;
 use crate::models::webrtc;
-use crate::service::webrtc::user;
-use actix::{Actor, Addr, Handler};
-use glib::ObjectExt;
+use crate::service::metrics;
+use crate::service::recording as recording_service;
+use crate::service::reporting;
+use crate::service::room as room_service;
+use crate::service::webrtc::{supervisor as supervisor_service, user};
+use actix::{
+    Actor, ActorContext, ActorFuture, Addr, AsyncContext, Handler, ResponseActFuture, SpawnHandle,
+    WrapFuture,
+};
+use futures::channel::oneshot;
+use futures::future;
+use glib::{Cast, ObjectExt};
 use gstreamer;
-use gstreamer::{ElementExt, ElementExtManual, GstBinExt, PadExt, PadExtManual};
+use gstreamer::{ElementExt, ElementExtManual, GstBinExt, GstObjectExt, PadExt, PadExtManual};
 use log::info;
-use std::collections::BTreeMap;
+use sentry::Level;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const STATS_DEAD_INTERVALS: u32 = 3;
+const LEVEL_STALE_INTERVALS: u32 = 3;
+const RECORDING_MIX: &str = "mix:recording";
+const RECORDING_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+pub struct ChannelConfig {
+    pub grace_period: Duration,
+    pub stats_interval: Duration,
+    pub stats_push_to_master: bool,
+    pub opus_bitrate: i32,
+    pub rtp_payload_type: u32,
+    pub speaker_detection: Option<SpeakerDetection>,
+    pub recording_directory: PathBuf,
+    pub recording_mixed: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct SpeakerDetection {
+    pub interval: Duration,
+    pub threshold_db: f64,
+    pub margin_db: f64,
+    pub hold: Duration,
+}
 
 pub struct Channel {
+    room_name: String,
+    room_address: Addr<room_service::Room>,
     users: Arc<Mutex<BTreeMap<String, user::User>>>,
     peers: Mutex<BTreeMap<String, user::User>>,
     pipeline_gstreamer: Arc<Mutex<webrtc::GstreamerPipeline>>,
-    nats: Vec<network_transversal::STUNTURN>,
+    nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+    supervisor_address: Addr<supervisor_service::Supervisor>,
+    stats: Arc<Mutex<BTreeMap<String, webrtc::WebRTCStats>>>,
+    config: ChannelConfig,
+    media: supervisor::RoomMedia,
+    ready: Mutex<BTreeSet<String>>,
+    decoders: Mutex<BTreeMap<String, webrtc::Decoder>>,
+    mixes: Mutex<BTreeMap<String, webrtc::Mix>>,
+    levels: BTreeMap<String, (f64, Instant)>,
+    active_speaker: Option<String>,
+    speaker_candidate: Option<(String, Instant)>,
+    recording: Mutex<Option<webrtc::Recording>>,
+    stop_handle: Option<SpawnHandle>,
+    stopping: bool,
 }
 
 impl Channel {
-    pub fn new(room_name: &str, nats: Vec<network_transversal::STUNTURN>) -> Addr<Channel> {
+    pub fn new(
+        room_name: &str,
+        nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+        room_address: Addr<room_service::Room>,
+        supervisor_address: Addr<supervisor_service::Supervisor>,
+        config: ChannelConfig,
+        media: supervisor::RoomMedia,
+    ) -> Addr<Channel> {
         let pipeline = gstreamer::Pipeline::new(Some(room_name));
-        let pipeline_gstreamer =
-            Arc::new(Mutex::new(webrtc::GstreamerPipeline { pipeline: pipeline }));
+        let pipeline_gstreamer = Arc::new(Mutex::new(webrtc::GstreamerPipeline { pipeline }));
         let channel = Channel {
+            room_name: room_name.to_owned(),
+            room_address,
             users: Arc::new(Mutex::new(BTreeMap::new())),
             peers: Mutex::new(BTreeMap::new()),
-            pipeline_gstreamer: pipeline_gstreamer,
-            nats: nats,
+            pipeline_gstreamer,
+            nats,
+            supervisor_address,
+            stats: Arc::new(Mutex::new(BTreeMap::new())),
+            config,
+            media,
+            ready: Mutex::new(BTreeSet::new()),
+            decoders: Mutex::new(BTreeMap::new()),
+            mixes: Mutex::new(BTreeMap::new()),
+            levels: BTreeMap::new(),
+            active_speaker: None,
+            speaker_candidate: None,
+            recording: Mutex::new(None),
+            stop_handle: None,
+            stopping: false,
         };
         channel.start()
     }
 
-    async fn heartbeat(
-        arc_users: Arc<Mutex<BTreeMap<String, user::User>>>,
-        pipeline_gstreamer: Arc<Mutex<webrtc::GstreamerPipeline>>,
-    ) {
-        for (uuid, user) in arc_users.lock().unwrap().iter() {
-            let pipeline = pipeline_gstreamer.lock().unwrap();
-            let uuid_clone = uuid.clone();
-            let room_name_clone = user.room_name.clone();
-            let room_address_clone = user.room_address.clone();
-            let promise = gstreamer::Promise::with_change_func(move |reply| {
-                if reply.unwrap().unwrap().n_fields() < 7 {
-                    let user_disconnected_json_message =
-                        serde_json::to_string(&message_websocket::UserStatus {
-                            action: "UserLeave",
-                            uuid: &uuid_clone,
-                        })
-                        .unwrap();
-
-                    info!("[OFFLINE] [{}]", uuid_clone);
-                    room_address_clone.do_send(room::Broadcast {
-                        uuid: uuid_clone,
-                        room_name: room_name_clone,
-                        message: user_disconnected_json_message,
-                    });
+    fn watch_bus(&self, context: &mut actix::Context<Self>) {
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+        let bus = pipeline_gstreamer.pipeline.get_bus().unwrap();
+        let room_name = self.room_name.clone();
+        let channel_address = Mutex::new(context.address());
+
+        // no glib main loop runs in this process, so bus messages are handled on the
+        // posting thread and dropped instead of piling up on the bus
+        bus.set_sync_handler(move |_, message| {
+            match message.view() {
+                gstreamer::MessageView::Error(err) => {
+                    let element = err
+                        .get_src()
+                        .map(|src| src.get_name().to_string())
+                        .unwrap_or_default();
+                    let error = format!("{} ({:?})", err.get_error(), err.get_debug());
+                    info!(
+                        "[ROOM: {}] [ELEMENT: {}] [GSTREAMER ERROR: {}]",
+                        room_name, element, error
+                    );
+
+                    // webrtcbin posts errors from its internal elements (nicesrc, dtlsdec, ...),
+                    // so the whole parent chain is handed over to find the owning user
+                    let mut elements = Vec::new();
+                    let mut src = err.get_src();
+                    while let Some(object) = src {
+                        elements.push(object.get_name().to_string());
+                        src = object.get_parent();
+                    }
+                    channel_address
+                        .lock()
+                        .unwrap()
+                        .do_send(webrtc::PipelineError { elements, error });
+                }
+                gstreamer::MessageView::Element(element) => {
+                    let level = element
+                        .get_structure()
+                        .filter(|structure| structure.get_name() == "level")
+                        .and_then(rms_level);
+                    let element_name = element
+                        .get_src()
+                        .map(|src| src.get_name().to_string())
+                        .unwrap_or_default();
+                    if let (Some(level), Some(uuid)) = (level, element_name.strip_suffix("_level"))
+                    {
+                        channel_address.lock().unwrap().do_send(webrtc::AudioLevel {
+                            uuid: uuid.to_string(),
+                            level,
+                        });
+                    }
+                }
+                gstreamer::MessageView::Warning(warning) => {
+                    let element = warning
+                        .get_src()
+                        .map(|src| src.get_name().to_string())
+                        .unwrap_or_default();
+                    info!(
+                        "[ROOM: {}] [ELEMENT: {}] [GSTREAMER WARNING: {} ({:?})]",
+                        room_name,
+                        element,
+                        warning.get_error(),
+                        warning.get_debug()
+                    );
+                }
+                _ => {}
+            }
+            gstreamer::BusSyncReply::Drop
+        });
+    }
+
+    // a challenger has to stay louder by margin_db for the whole hold time
+    fn update_speaker(&mut self) {
+        let detection = match self.config.speaker_detection {
+            Some(detection) => detection,
+            None => return,
+        };
+        let users = self.users.lock().unwrap();
+        let stale_after = detection.interval * LEVEL_STALE_INTERVALS;
+        self.levels.retain(|uuid, (_, received)| {
+            users.contains_key(uuid) && received.elapsed() < stale_after
+        });
+        let muted: BTreeSet<String> = users
+            .iter()
+            .filter(|(uuid, user)| self.is_muted(uuid, user))
+            .map(|(uuid, _)| uuid.clone())
+            .collect();
+        drop(users);
+
+        let loudest = self
+            .levels
+            .iter()
+            .filter(|(uuid, (level, _))| *level > detection.threshold_db && !muted.contains(*uuid))
+            .max_by(|(_, (a, _)), (_, (b, _))| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(uuid, (level, _))| (uuid.clone(), *level));
+        let active_level = self
+            .active_speaker
+            .as_ref()
+            .and_then(|uuid| self.levels.get(uuid))
+            .map(|(level, _)| *level)
+            .unwrap_or(f64::NEG_INFINITY);
+
+        let challenger = loudest.filter(|(uuid, level)| {
+            self.active_speaker.as_ref() != Some(uuid)
+                && (active_level <= detection.threshold_db
+                    || *level >= active_level + detection.margin_db)
+        });
+        match challenger {
+            Some((uuid, _)) => {
+                let held_since = match &self.speaker_candidate {
+                    Some((candidate, since)) if *candidate == uuid => Some(*since),
+                    _ => None,
+                };
+                match held_since {
+                    Some(since) if since.elapsed() >= detection.hold => {
+                        self.set_active_speaker(Some(uuid))
+                    }
+                    Some(_) => {}
+                    None => self.speaker_candidate = Some((uuid, Instant::now())),
                 }
+            }
+            None => self.speaker_candidate = None,
+        }
+
+        let is_gone = match &self.active_speaker {
+            Some(uuid) => !self.users.lock().unwrap().contains_key(uuid),
+            None => false,
+        };
+        if is_gone {
+            self.set_active_speaker(None);
+        }
+
+        if !self.levels.is_empty() {
+            let message_levels = serde_json::to_string(&message_websocket::AudioLevelsStatus {
+                action: "AudioLevels",
+                levels: self
+                    .levels
+                    .iter()
+                    .map(|(uuid, (level, _))| (uuid.as_str(), *level))
+                    .collect(),
+            })
+            .unwrap();
+            self.room_address.do_send(room::Broadcast {
+                room_name: self.room_name.clone(),
+                uuid: "wigglypuff".to_string(),
+                message: message_levels,
             });
-            let webrtcbin = pipeline
+        }
+    }
+
+    fn set_active_speaker(&mut self, uuid: Option<String>) {
+        self.speaker_candidate = None;
+        if self.active_speaker == uuid {
+            return;
+        }
+        info!("[ROOM: {}] [ACTIVE SPEAKER: {:?}]", self.room_name, uuid);
+        self.active_speaker = uuid;
+
+        let message_speaker = serde_json::to_string(&message_websocket::SpeakerStatus {
+            action: "SpeakerChanged",
+            uuid: self.active_speaker.as_deref(),
+        })
+        .unwrap();
+        self.room_address.do_send(room::Broadcast {
+            room_name: self.room_name.clone(),
+            uuid: "wigglypuff".to_string(),
+            message: message_speaker,
+        });
+    }
+
+    fn collect_stats(&self, context: &mut actix::Context<Self>) {
+        let users = self.users.lock().unwrap();
+        let peers = self.peers.lock().unwrap();
+        self.stats
+            .lock()
+            .unwrap()
+            .retain(|uuid, _| users.contains_key(uuid) || peers.contains_key(uuid));
+
+        for (uuid, user) in users.iter().chain(peers.iter()) {
+            let webrtcbin = match user
                 .pipeline
+                .webrtcbin
                 .get_by_name(&format!("{}_webrtcbin", uuid))
-                .unwrap();
+            {
+                Some(webrtcbin) => webrtcbin,
+                None => continue,
+            };
+            let ice_connection_state = ice_connection_state(&webrtcbin);
+            let uuid_clone = uuid.clone();
+            // muted or listen-only producers legitimately stop sending
+            let expects_media = user.pipeline.role == webrtc::Role::Producer
+                && !self.is_muted(uuid, user)
+                && user
+                    .pipeline
+                    .webrtcbin
+                    .get_static_pad(&format!("{}_audiosrc", uuid))
+                    .is_some();
+            let stats = self.stats.clone();
+            let channel_address = context.address();
+            let promise = gstreamer::Promise::with_change_func(move |reply| {
+                let reply = match reply {
+                    Ok(Some(reply)) => reply,
+                    _ => return,
+                };
+                let mut webrtc_stats = parse_stats(reply, ice_connection_state);
+                let mut stats = stats.lock().unwrap();
+                let was_alive = match stats.get(&uuid_clone) {
+                    Some(previous) => {
+                        update_liveness(&mut webrtc_stats, previous, expects_media);
+                        previous.is_alive
+                    }
+                    None => {
+                        update_liveness(&mut webrtc_stats, &Default::default(), expects_media);
+                        false
+                    }
+                };
+                let is_lost = was_alive && !webrtc_stats.is_alive;
+                stats.insert(uuid_clone.clone(), webrtc_stats);
+                drop(stats);
+
+                if is_lost {
+                    channel_address.do_send(webrtc::ConnectionLost { uuid: uuid_clone });
+                }
+            });
             webrtcbin
                 .emit("get-stats", &[&None::<gstreamer::Pad>, &promise])
                 .unwrap();
         }
+
+        if self.config.stats_push_to_master {
+            let channel_stats = self.channel_stats(&users, &peers);
+            self.room_address.do_send(room::SendMaster {
+                room_name: self.room_name.clone(),
+                message: serde_json::to_string(&message_websocket::StatsStatus {
+                    action: "WebRTCStats",
+                    stats: &channel_stats,
+                })
+                .unwrap(),
+            });
+        }
+    }
+
+    fn channel_stats(
+        &self,
+        users: &BTreeMap<String, user::User>,
+        peers: &BTreeMap<String, user::User>,
+    ) -> webrtc::ChannelStats {
+        let mut channel_stats = webrtc::ChannelStats::default();
+        for (uuid, webrtc_stats) in self.stats.lock().unwrap().iter() {
+            if users.contains_key(uuid) {
+                channel_stats
+                    .users
+                    .insert(uuid.clone(), webrtc_stats.clone());
+            } else if peers.contains_key(uuid) {
+                channel_stats
+                    .peers
+                    .insert(uuid.clone(), webrtc_stats.clone());
+            }
+        }
+
+        channel_stats
     }
 
     fn create_fakeaudio(&self, uuid: &str) -> gstreamer::Bin {
@@ -74,8 +377,9 @@ impl Channel {
 
         let fakeaudio = gstreamer::parse_bin_from_description(
             &format!(
-                "audiotestsrc wave=sine is-live=true ! opusenc name={uuid}_opusenc",
-                uuid = uuid
+                "audiotestsrc wave=sine is-live=true ! opusenc name={uuid}_opusenc bitrate={bitrate}",
+                uuid = uuid,
+                bitrate = self.config.opus_bitrate,
             ),
             false,
         )
@@ -98,19 +402,26 @@ impl Channel {
     fn create_webrtcbin(&self, uuid: &str) -> gstreamer::Bin {
         let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
 
-        let stun_address = match &self.nats[0] {
-            network_transversal::STUNTURN::STUN { url, .. } => {
-                Some(url.clone().replace("stun:", "stun://"))
+        let stun_uri = self
+            .nats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|nat| nat.stun_uris())
+            .next();
+        let stun_server = match stun_uri {
+            Some(stun_address) => {
+                info!("[UUID: {}] [STUN SERVER: {}]", uuid, stun_address);
+                format!(" stun-server={}", stun_address)
             }
-            _ => None,
+            None => String::new(),
         };
-        let stun_address = stun_address.unwrap();
-        info!("{}", stun_address);
 
         let user = gstreamer::parse_bin_from_description(&format!(
-            "rtpopuspay name={uuid}_rtpopuspay pt=97 ! webrtcbin name={uuid}_webrtcbin bundle-policy=max-bundle stun-server={stun_server}",
+            "rtpopuspay name={uuid}_rtpopuspay pt={payload_type} ! webrtcbin name={uuid}_webrtcbin bundle-policy=max-bundle{stun_server}",
             uuid = uuid,
-            stun_server = stun_address,
+            payload_type = self.config.rtp_payload_type,
+            stun_server = stun_server,
         ), false).unwrap();
 
         let rtpopuspay = user
@@ -163,16 +474,20 @@ impl Channel {
         let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
 
         let teebin = gstreamer::parse_bin_from_description(
-            &format!("tee name={uuid}_tee", uuid = uuid),
+            &format!(
+                "valve name={uuid}_valve drop=false ! tee name={uuid}_tee",
+                uuid = uuid
+            ),
             false,
         )
         .unwrap();
 
+        let valve = teebin.get_by_name(&format!("{}_valve", uuid)).unwrap();
         let tee = teebin.get_by_name(&format!("{}_tee", uuid)).unwrap();
 
         let tee_sink = gstreamer::GhostPad::with_target(
             Some(&format!("{}_tee_sink", uuid)),
-            &tee.get_static_pad("sink").unwrap(),
+            &valve.get_static_pad("sink").unwrap(),
         )
         .unwrap();
         tee_sink.set_active(true).unwrap();
@@ -191,29 +506,208 @@ impl Channel {
         teebin
     }
 
+    fn set_valve(&self, uuid: &str, user: &user::User, muted: bool) {
+        if let Some(valve) = user.pipeline.tee.get_by_name(&format!("{}_valve", uuid)) {
+            valve.set_property("drop", &muted).unwrap();
+        }
+    }
+
+    fn is_muted(&self, uuid: &str, user: &user::User) -> bool {
+        user.pipeline
+            .tee
+            .get_by_name(&format!("{}_valve", uuid))
+            .and_then(|valve| valve.get_property("drop").ok())
+            .and_then(|drop| drop.get_some::<bool>().ok())
+            .unwrap_or(false)
+    }
+
+    fn release_user(
+        &self,
+        users: &mut BTreeMap<String, user::User>,
+        peers: &mut BTreeMap<String, user::User>,
+        uuid: &str,
+    ) {
+        let peer_src = format!("src:{}_sink:", uuid);
+        let peer_sink = format!("_sink:{}", uuid);
+        let peer_keys: Vec<String> = peers
+            .keys()
+            .filter(|peer_key| peer_key.starts_with(&peer_src) || peer_key.ends_with(&peer_sink))
+            .cloned()
+            .collect();
+
+        for peer_key in peer_keys {
+            if let Some(peer) = peers.remove(&peer_key) {
+                metrics::CONSUMER_PEERS.dec();
+                self.release_user_pipeline(&peer_key, &peer.pipeline);
+            }
+        }
+
+        // bundled tracks hold tee branches on both sides, they go before any teebin does
+        for (uuid_sink, user_sink) in users.iter() {
+            if uuid_sink != uuid {
+                self.release_track(user_sink, uuid, true);
+            }
+        }
+        if let Some(producer) = users.get(uuid) {
+            let sources: Vec<String> = producer.tracks.lock().unwrap().keys().cloned().collect();
+            for src in sources {
+                self.release_track(producer, &src, false);
+            }
+        }
+        if let Some(recording) = self.recording.lock().unwrap().as_mut() {
+            self.finish_recorder(recording, uuid, true);
+        }
+        self.release_mixing(users, uuid);
+
+        self.ready.lock().unwrap().remove(uuid);
+        if let Some(producer) = users.remove(uuid) {
+            metrics::PRODUCERS.dec();
+            self.release_user_pipeline(uuid, &producer.pipeline);
+        }
+    }
+
+    fn wants_pair(&self, src: &str, sink: &str) -> bool {
+        src != sink
+            && match self.media.pairing {
+                supervisor::PairingPolicy::AllToAll => true,
+                supervisor::PairingPolicy::TeacherToAll => {
+                    src == self.media.master_uuid || sink == self.media.master_uuid
+                }
+                supervisor::PairingPolicy::Explicit => false,
+            }
+    }
+
+    fn pair(
+        &self,
+        users: &BTreeMap<String, user::User>,
+        peers: &mut BTreeMap<String, user::User>,
+        src: &str,
+        sink: &str,
+        context: &mut actix::Context<Self>,
+    ) -> bool {
+        match self.media.transport {
+            supervisor::Transport::Bundle => return self.add_track(users, src, sink),
+            supervisor::Transport::Mcu => return self.mix_in(users, src, sink),
+            supervisor::Transport::PerPeer => {}
+        }
+
+        let peer_key = format!("src:{}_sink:{}", src, sink);
+        let user_src = match users.get(src) {
+            Some(user_src) if !peers.contains_key(&peer_key) => user_src,
+            _ => return false,
+        };
+
+        let user_pipeline = self.build_consumer(&peer_key, &user_src.pipeline.tee);
+        let new_user = user::User::new(
+            self.room_address.clone(),
+            context.address(),
+            &self.room_name,
+            &peer_key,
+            user_pipeline,
+            self.nats.clone(),
+            false,
+            None,
+        )
+        .unwrap();
+        peers.insert(peer_key, new_user);
+        metrics::CONSUMER_PEERS.inc();
+        true
+    }
+
     fn play_pipeline(&self) {
         let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+        let room_name = self.room_name.clone();
 
         pipeline_gstreamer.pipeline.call_async(move |pipeline| {
             if pipeline.set_state(gstreamer::State::Playing).is_err() {
-                info!("Failed to set pipeline to Playing");
+                info!("[ROOM: {}] [FAILED TO SET PIPELINE TO PLAYING]", room_name);
+            }
+        });
+    }
+
+    fn element_owner(&self, elements: &[String]) -> Option<String> {
+        let users = self.users.lock().unwrap();
+        let peers = self.peers.lock().unwrap();
+
+        let track_keys: Vec<String> = users
+            .iter()
+            .flat_map(|(uuid_sink, user_sink)| {
+                user_sink
+                    .tracks
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .map(|uuid_src| format!("src:{}_sink:{}", uuid_src, uuid_sink))
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+
+        elements.iter().find_map(|element| {
+            users
+                .keys()
+                .chain(peers.keys())
+                .chain(track_keys.iter())
+                .filter(|key| element.starts_with(&format!("{}_", key)))
+                .max_by_key(|key| key.len())
+                .cloned()
+        })
+    }
+
+    fn release_peer(&self, peer_key: &str) -> bool {
+        let peer = self.peers.lock().unwrap().remove(peer_key);
+        match peer {
+            Some(peer) => {
+                metrics::CONSUMER_PEERS.dec();
+                self.release_user_pipeline(peer_key, &peer.pipeline);
+                self.stats.lock().unwrap().remove(peer_key);
+                true
+            }
+            None => {
+                let uuid_split: Vec<&str> = peer_key.split("_sink:").collect();
+                let users = self.users.lock().unwrap();
+                match (uuid_split[0].get(4..), users.get(uuid_split[1])) {
+                    (Some(uuid_src), Some(user_sink)) => {
+                        self.release_track(user_sink, uuid_src, true)
+                    }
+                    _ => false,
+                }
             }
+        }
+    }
+
+    fn send_user_status(&self, uuid: &str, action: &str, about: &str) {
+        let message = serde_json::to_string(&message_websocket::UserStatus {
+            action,
+            uuid: about,
+        })
+        .unwrap();
+        self.room_address.do_send(room::SendUser {
+            room_name: self.room_name.clone(),
+            uuid: uuid.to_string(),
+            message,
         });
+    }
 
-        pipeline_gstreamer.pipeline.call_async(|pipeline| {
-            pipeline
-                .set_state(gstreamer::State::Playing)
-                .expect("Couldn't set pipeline to Playing");
+    fn broadcast_user_status(&self, action: &str, about: &str) {
+        let message = serde_json::to_string(&message_websocket::UserStatus {
+            action,
+            uuid: about,
+        })
+        .unwrap();
+        self.room_address.do_send(room::Broadcast {
+            room_name: self.room_name.clone(),
+            uuid: "wigglypuff".to_string(),
+            message,
         });
     }
 
     fn build_producer(&self, uuid: &str) -> webrtc::UserPipeline {
         let role = webrtc::Role::Producer;
 
-        let fakeaudio = self.create_fakeaudio(&uuid);
-        let webrtcbin = self.create_webrtcbin(&uuid);
-        let tee = self.create_teeadapter(&uuid);
-        let fakesink = self.create_fakesink(&uuid);
+        let fakeaudio = self.create_fakeaudio(uuid);
+        let webrtcbin = self.create_webrtcbin(uuid);
+        let tee = self.create_teeadapter(uuid);
+        let fakesink = self.create_fakesink(uuid);
         fakeaudio.link(&webrtcbin).unwrap();
 
         webrtc::UserPipeline {
@@ -222,6 +716,7 @@ impl Channel {
             tee,
             fakesink,
             role,
+            tee_branch: None,
         }
     }
 
@@ -232,10 +727,10 @@ impl Channel {
     ) -> webrtc::UserPipeline {
         let role = webrtc::Role::Consumer;
 
-        let fakeaudio = self.create_fakeaudio(&uuid);
-        let webrtcbin = self.create_webrtcbin(&uuid);
-        let tee = self.create_teeadapter(&uuid);
-        let fakesink = self.create_fakesink(&uuid);
+        let fakeaudio = self.create_fakeaudio(uuid);
+        let webrtcbin = self.create_webrtcbin(uuid);
+        let tee = self.create_teeadapter(uuid);
+        let fakesink = self.create_fakesink(uuid);
 
         fakeaudio.sync_state_with_parent().unwrap();
         webrtcbin.sync_state_with_parent().unwrap();
@@ -244,7 +739,27 @@ impl Channel {
 
         let uuid_split: Vec<&str> = uuid.split("_sink:").collect();
         let uuid_src = uuid_split[0][4..].to_string();
+        let audio_sink_pad = webrtcbin
+            .get_static_pad(&format!("{}_audiosink", uuid))
+            .unwrap();
+        let tee_branch = self.create_tee_branch(&uuid_src, teebin_from_uuid_src, &audio_sink_pad);
 
+        webrtc::UserPipeline {
+            fakeaudio,
+            webrtcbin,
+            tee,
+            fakesink,
+            role,
+            tee_branch: Some(tee_branch),
+        }
+    }
+
+    fn create_tee_branch(
+        &self,
+        uuid_src: &str,
+        teebin_from_uuid_src: &gstreamer::Bin,
+        sink_pad: &gstreamer::Pad,
+    ) -> webrtc::TeeBranch {
         let tee_src = teebin_from_uuid_src
             .get_by_name(&format!("{}_tee", uuid_src))
             .unwrap();
@@ -266,158 +781,1034 @@ impl Channel {
         let teesrc_pad = gstreamer::GhostPad::with_target(None, &queue_src_pad).unwrap();
         teesrc_pad.set_active(true).unwrap();
         teebin_from_uuid_src.add_pad(&teesrc_pad).unwrap();
-        teebin_from_uuid_src.link(&webrtcbin).unwrap();
+        teesrc_pad.link(sink_pad).unwrap();
         audio_src_pad.remove_probe(audio_block);
 
-        webrtc::UserPipeline {
-            fakeaudio,
-            webrtcbin,
-            tee,
-            fakesink,
-            role,
+        webrtc::TeeBranch {
+            tee_pad: audio_src_pad,
+            queue,
+            ghost_pad: teesrc_pad,
+            teebin: teebin_from_uuid_src.clone(),
         }
     }
-}
-
-impl Actor for Channel {
-    type Context = actix::Context<Self>;
 
-    fn started(&mut self, _context: &mut Self::Context) {
-        // context.run_interval(Duration::from_millis(5000), |this, _ctx| {
-        //     Arbiter::spawn(Channel::heartbeat(
-        //         this.users.clone(),
-        //         this.pipeline_gstreamer.clone(),
-        //     ));
-        // });
+    fn add_track(&self, users: &BTreeMap<String, user::User>, src: &str, sink: &str) -> bool {
+        match (users.get(src), users.get(sink)) {
+            (Some(user_src), Some(user_sink)) => {
+                self.attach_track(src, &user_src.pipeline.tee, user_sink, sink)
+            }
+            _ => false,
+        }
     }
-}
 
-impl Handler<webrtc::RequestPair> for Channel {
-    type Result = ();
-
-    fn handle(&mut self, user: webrtc::RequestPair, _: &mut actix::Context<Self>) {
+    fn attach_track(
+        &self,
+        src: &str,
+        teebin: &gstreamer::Bin,
+        user_sink: &user::User,
+        sink: &str,
+    ) -> bool {
+        let mut tracks = user_sink.tracks.lock().unwrap();
+        if tracks.contains_key(src) {
+            return false;
+        }
+        let peer_key = format!("src:{}_sink:{}", src, sink);
         info!(
-            "[ROOM: {}] [UUID: {}] [TARGET: {}] [GET PAIR REQUEST FROM CHANNEL]",
-            user.room_name, user.from_uuid, user.uuid
+            "[ROOM: {}] [UUID: {}] [ADDING BUNDLED TRACK FROM: {}]",
+            self.room_name, sink, src
         );
-        let users = self.users.lock().unwrap();
-        let mut peers = self.peers.lock().unwrap();
 
-        if let Some(user_src) = users.get(&user.from_uuid) {
-            let peer_key = format!("src:{}_sink:{}", user.from_uuid, user.uuid);
-            let user_pipeline = self.build_consumer(&peer_key, &user_src.pipeline.tee);
-            let nats: Vec<network_transversal::STUNTURN> = self
-                .nats
-                .iter()
-                .map(|room_name| room_name.clone())
-                .collect();
-            let new_user = user::User::new(
-                user_src.room_address.clone(),
-                &user.room_name,
-                &peer_key,
-                user_pipeline,
-                nats,
-            )
+        let payloader = gstreamer::ElementFactory::make(
+            "rtpopuspay",
+            Some(&format!("{}_rtpopuspay", peer_key)),
+        )
+        .unwrap();
+        payloader
+            .set_property("pt", &self.config.rtp_payload_type)
             .unwrap();
-            peers.insert(peer_key, new_user);
-        }
-        drop(peers);
-        self.play_pipeline();
-        let mut peers = self.peers.lock().unwrap();
-        if let Some(user_src) = users.get(&user.uuid) {
-            let peer_key = format!("src:{}_sink:{}", user.uuid, user.from_uuid);
-            let user_pipeline = self.build_consumer(&peer_key, &user_src.pipeline.tee);
+        user_sink.pipeline.webrtcbin.add(&payloader).unwrap();
 
-            let nats: Vec<network_transversal::STUNTURN> = self
-                .nats
-                .iter()
-                .map(|room_name| room_name.clone())
-                .collect();
-            let new_user = user::User::new(
-                user_src.room_address.clone(),
-                &user.room_name,
-                &peer_key,
-                user_pipeline,
-                nats,
-            )
+        let ghost_pad = gstreamer::GhostPad::with_target(
+            Some(&format!("{}_audiosink", peer_key)),
+            &payloader.get_static_pad("sink").unwrap(),
+        )
+        .unwrap();
+        ghost_pad.set_active(true).unwrap();
+        user_sink.pipeline.webrtcbin.add_pad(&ghost_pad).unwrap();
+
+        // requesting the pad adds the transceiver and makes webrtcbin ask for negotiation
+        let webrtcbin = user_sink
+            .pipeline
+            .webrtcbin
+            .get_by_name(&format!("{}_webrtcbin", sink))
             .unwrap();
-            peers.insert(peer_key, new_user);
+        let webrtc_pad = webrtcbin.get_request_pad("sink_%u").unwrap();
+        let transceiver = webrtc_pad
+            .get_property("transceiver")
+            .ok()
+            .and_then(|value| value.get::<gstreamer_webrtc::WebRTCRTPTransceiver>().ok())
+            .flatten();
+        if let Some(transceiver) = &transceiver {
+            transceiver
+                .set_property(
+                    "direction",
+                    &gstreamer_webrtc::WebRTCRTPTransceiverDirection::Sendonly,
+                )
+                .unwrap();
         }
-        drop(peers);
-        self.play_pipeline();
-    }
-}
-impl Handler<supervisor::RegisterUser> for Channel {
-    type Result = ();
-
-    fn handle(&mut self, user: supervisor::RegisterUser, _: &mut actix::Context<Self>) {
-        let mut users = self.users.lock().unwrap();
+        payloader
+            .get_static_pad("src")
+            .unwrap()
+            .link(&webrtc_pad)
+            .unwrap();
+        payloader.sync_state_with_parent().unwrap();
 
-        let user_pipeline = self.build_producer(&user.uuid);
-        let nats: Vec<network_transversal::STUNTURN> = self
-            .nats
-            .iter()
-            .map(|room_name| room_name.clone())
-            .collect();
-        let new_user = user::User::new(
-            user.room_address.clone(),
-            &user.room_name,
-            &user.uuid,
-            user_pipeline,
-            nats,
-        )
-        .unwrap();
-        users.insert(user.uuid, new_user);
-        self.play_pipeline();
+        let tee_branch = self.create_tee_branch(src, teebin, ghost_pad.upcast_ref());
+        tracks.insert(
+            src.to_string(),
+            webrtc::Track {
+                tee_branch,
+                payloader,
+                ghost_pad,
+                webrtc_pad,
+                transceiver,
+            },
+        );
+        metrics::CONSUMER_PEERS.inc();
+        true
     }
-}
-
-impl Handler<webrtc::SessionDescription> for Channel {
-    type Result = ();
 
-    fn handle(&mut self, sdp: webrtc::SessionDescription, _: &mut actix::Context<Self>) {
+    fn release_track(&self, user_sink: &user::User, src: &str, renegotiate: bool) -> bool {
+        let track = match user_sink.tracks.lock().unwrap().remove(src) {
+            Some(track) => track,
+            None => return false,
+        };
         info!(
-            "[ROOM: {}] [FROM UUID: {}] [TO UUID: {}] [GET SDP FROM CHANNEL]",
-            sdp.room_name, sdp.from_uuid, sdp.uuid
+            "[ROOM: {}] [UUID: {}] [RELEASING BUNDLED TRACK FROM: {}]",
+            self.room_name, user_sink.uuid, src
         );
+        metrics::CONSUMER_PEERS.dec();
+        self.release_tee_branch(&track.tee_branch);
 
-        if sdp.from_uuid != sdp.uuid {
-            if sdp.uuid.contains("_sink") {
-                let peers = self.peers.lock().unwrap();
-                if let Some(peer) = peers.get(&sdp.uuid) {
-                    peer.set_session_to_gstreamer(sdp.sdp);
-                }
-            }
-        } else {
-            let users = self.users.lock().unwrap();
-            if let Some(user) = users.get(&sdp.from_uuid) {
-                user.set_session_to_gstreamer(sdp.sdp);
+        // webrtcbin never drops a transceiver, the m-line is left inactive with its pad unlinked
+        if let Some(transceiver) = &track.transceiver {
+            transceiver
+                .set_property(
+                    "direction",
+                    &gstreamer_webrtc::WebRTCRTPTransceiverDirection::Inactive,
+                )
+                .unwrap();
+        }
+        if let Some(payloader_src_pad) = track.payloader.get_static_pad("src") {
+            if payloader_src_pad.unlink(&track.webrtc_pad).is_err() {
+                info!("Failed to unlink bundled track from webrtcbin");
             }
         }
+        if track.payloader.set_state(gstreamer::State::Null).is_err() {
+            info!("Failed to set bundled track payloader to Null");
+        }
+        track.ghost_pad.set_active(false).unwrap();
+        if user_sink
+            .pipeline
+            .webrtcbin
+            .remove_pad(&track.ghost_pad)
+            .is_err()
+            || user_sink
+                .pipeline
+                .webrtcbin
+                .remove(&track.payloader)
+                .is_err()
+        {
+            info!("Failed to remove bundled track from webrtcbin bin");
+        }
+
+        if renegotiate {
+            user_sink.renegotiate();
+        }
+        true
     }
-}
 
-impl Handler<webrtc::ICECandidate> for Channel {
-    type Result = ();
+    fn create_decoder(&self, uuid: &str, user_src: &user::User) -> webrtc::Decoder {
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
 
-    fn handle(&mut self, ice: webrtc::ICECandidate, _: &mut actix::Context<Self>) {
-        info!(
-            "[ROOM: {}] [FROM UUID: {}] [TO UUID: {}] [GET ICE FROM CHANNEL]",
-            ice.room_name, ice.from_uuid, ice.uuid
-        );
+        let decoder = gstreamer::parse_bin_from_description(
+            &format!(
+                "queue name={uuid}_decoder_queue ! opusdec ! audioconvert ! audioresample ! audio/x-raw,rate=48000,channels=2 ! tee name={uuid}_decoder_tee allow-not-linked=true",
+                uuid = uuid
+            ),
+            false,
+        )
+        .unwrap();
 
-        if ice.from_uuid != ice.uuid {
-            if ice.uuid.contains("_sink") {
-                let peers = self.peers.lock().unwrap();
-                if let Some(peer) = peers.get(&ice.uuid) {
-                    peer.set_ice_to_gstreamer(ice.sdp_mline_index, ice.candidate);
-                }
-            }
-        } else {
-            let users = self.users.lock().unwrap();
-            if let Some(user) = users.get(&ice.from_uuid) {
-                user.set_ice_to_gstreamer(ice.sdp_mline_index, ice.candidate);
-            }
+        let queue = decoder
+            .get_by_name(&format!("{}_decoder_queue", uuid))
+            .unwrap();
+        let decoder_sink = gstreamer::GhostPad::with_target(
+            Some(&format!("{}_decoder_sink", uuid)),
+            &queue.get_static_pad("sink").unwrap(),
+        )
+        .unwrap();
+        decoder_sink.set_active(true).unwrap();
+        decoder.add_pad(&decoder_sink).unwrap();
+
+        pipeline_gstreamer.pipeline.add(&decoder).unwrap();
+        drop(pipeline_gstreamer);
+        decoder.sync_state_with_parent().unwrap();
+
+        let tee_branch =
+            self.create_tee_branch(uuid, &user_src.pipeline.tee, decoder_sink.upcast_ref());
+        webrtc::Decoder {
+            bin: decoder,
+            tee_branch,
+        }
+    }
+
+    fn create_mix(&self, mix_key: &str) -> webrtc::Mix {
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+
+        let mix = gstreamer::parse_bin_from_description(
+            &format!(
+                "audiomixer name={mix}_audiomixer start-time-selection=first ! audioconvert ! audioresample ! opusenc bitrate={bitrate} ! tee name={mix}_tee allow-not-linked=true",
+                mix = mix_key,
+                bitrate = self.config.opus_bitrate,
+            ),
+            false,
+        )
+        .unwrap();
+
+        pipeline_gstreamer.pipeline.add(&mix).unwrap();
+        drop(pipeline_gstreamer);
+        mix.sync_state_with_parent().unwrap();
+
+        webrtc::Mix {
+            bin: mix,
+            inputs: BTreeMap::new(),
+        }
+    }
+
+    fn create_mix_input(
+        &self,
+        src: &str,
+        decoder: &webrtc::Decoder,
+        mix_key: &str,
+        mix: &webrtc::Mix,
+    ) -> webrtc::MixInput {
+        let audiomixer = mix
+            .bin
+            .get_by_name(&format!("{}_audiomixer", mix_key))
+            .unwrap();
+        let mixer_pad = audiomixer.get_request_pad("sink_%u").unwrap();
+
+        let ghost_pad = gstreamer::GhostPad::with_target(
+            Some(&format!("{}_{}_mixsink", mix_key, src)),
+            &mixer_pad,
+        )
+        .unwrap();
+        ghost_pad.set_active(true).unwrap();
+        mix.bin.add_pad(&ghost_pad).unwrap();
+
+        let tee_branch = self.create_tee_branch(
+            &format!("{}_decoder", src),
+            &decoder.bin,
+            ghost_pad.upcast_ref(),
+        );
+        webrtc::MixInput {
+            tee_branch,
+            ghost_pad,
+            mixer_pad,
+        }
+    }
+
+    fn release_mix_input(&self, mix: &webrtc::Mix, mix_input: &webrtc::MixInput) {
+        self.release_tee_branch(&mix_input.tee_branch);
+        mix_input.ghost_pad.set_active(false).unwrap();
+        if mix.bin.remove_pad(&mix_input.ghost_pad).is_err() {
+            info!("Failed to remove mix input pad");
+        }
+        if let Some(audiomixer) = mix_input.mixer_pad.get_parent_element() {
+            audiomixer.release_request_pad(&mix_input.mixer_pad);
+        }
+    }
+
+    fn release_bin(&self, bin: &gstreamer::Bin) {
+        if bin.set_state(gstreamer::State::Null).is_err() {
+            info!("[ROOM: {}] Failed to set bin to Null", self.room_name);
+        }
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+        if pipeline_gstreamer.pipeline.remove(bin).is_err() {
+            info!(
+                "[ROOM: {}] Failed to remove bin from pipeline",
+                self.room_name
+            );
+        }
+    }
+
+    // silent listeners share one all-to-all mix, everyone else gets an N-1 mix
+    fn listener_mix(&self, sink: &str) -> String {
+        match self.media.pairing == supervisor::PairingPolicy::AllToAll
+            && !self.ready.lock().unwrap().contains(sink)
+        {
+            true => "mix:all".to_string(),
+            false => format!("mix:{}", sink),
+        }
+    }
+
+    fn mix_entry<'a>(
+        &self,
+        mixes: &'a mut BTreeMap<String, webrtc::Mix>,
+        mix_key: &str,
+    ) -> &'a mut webrtc::Mix {
+        if !mixes.contains_key(mix_key) {
+            info!("[ROOM: {}] [CREATING MIX: {}]", self.room_name, mix_key);
+            mixes.insert(mix_key.to_string(), self.create_mix(mix_key));
+        }
+        mixes.get_mut(mix_key).unwrap()
+    }
+
+    fn mix_in(&self, users: &BTreeMap<String, user::User>, src: &str, sink: &str) -> bool {
+        let (user_src, user_sink) = match (users.get(src), users.get(sink)) {
+            (Some(user_src), Some(user_sink)) if src != sink => (user_src, user_sink),
+            _ => return false,
+        };
+        let mix_key = self.listener_mix(sink);
+        let mut mixes = self.mixes.lock().unwrap();
+        let mix = self.mix_entry(&mut mixes, &mix_key);
+
+        let listening = self.attach_track(&mix_key, &mix.bin, user_sink, sink);
+        let mixing = self.feed_mix(user_src, src, &mix_key, mix);
+        listening || mixing
+    }
+
+    fn feed_mix(
+        &self,
+        user_src: &user::User,
+        src: &str,
+        mix_key: &str,
+        mix: &mut webrtc::Mix,
+    ) -> bool {
+        if mix.inputs.contains_key(src) {
+            return false;
+        }
+        info!(
+            "[ROOM: {}] [UUID: {}] [MIXING INTO: {}]",
+            self.room_name, src, mix_key
+        );
+        let mut decoders = self.decoders.lock().unwrap();
+        if !decoders.contains_key(src) {
+            decoders.insert(src.to_string(), self.create_decoder(src, user_src));
+        }
+        let mix_input = self.create_mix_input(src, &decoders[src], mix_key, mix);
+        mix.inputs.insert(src.to_string(), mix_input);
+        true
+    }
+
+    fn release_mixing(&self, users: &BTreeMap<String, user::User>, uuid: &str) {
+        let mut mixes = self.mixes.lock().unwrap();
+        for mix in mixes.values_mut() {
+            if let Some(mix_input) = mix.inputs.remove(uuid) {
+                self.release_mix_input(mix, &mix_input);
+            }
+        }
+
+        let unheard: Vec<String> = mixes
+            .keys()
+            .filter(|mix_key| {
+                *mix_key != RECORDING_MIX
+                    && !users.iter().any(|(uuid_sink, user_sink)| {
+                        uuid_sink != uuid && user_sink.tracks.lock().unwrap().contains_key(*mix_key)
+                    })
+            })
+            .cloned()
+            .collect();
+        for mix_key in unheard {
+            let mix = mixes.remove(&mix_key).unwrap();
+            info!("[ROOM: {}] [RELEASING MIX: {}]", self.room_name, mix_key);
+            for mix_input in mix.inputs.values() {
+                self.release_mix_input(&mix, mix_input);
+            }
+            self.release_bin(&mix.bin);
+        }
+        drop(mixes);
+
+        if let Some(decoder) = self.decoders.lock().unwrap().remove(uuid) {
+            self.release_tee_branch(&decoder.tee_branch);
+            self.release_bin(&decoder.bin);
+        }
+    }
+
+    fn create_recorder(
+        &self,
+        key: &str,
+        teebin: &gstreamer::Bin,
+        location: &Path,
+        file: String,
+    ) -> webrtc::Recorder {
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+
+        let recorder = gstreamer::parse_bin_from_description(
+            &format!(
+                "queue name={key}_recorder_queue ! opusparse ! oggmux ! filesink name={key}_recorder_filesink async=false",
+                key = key
+            ),
+            false,
+        )
+        .unwrap();
+        recorder
+            .set_property("name", &format!("{}_recorder", key))
+            .unwrap();
+
+        let filesink = recorder
+            .get_by_name(&format!("{}_recorder_filesink", key))
+            .unwrap();
+        filesink
+            .set_property("location", &location.to_string_lossy().to_string())
+            .unwrap();
+
+        let queue = recorder
+            .get_by_name(&format!("{}_recorder_queue", key))
+            .unwrap();
+        let recorder_sink = gstreamer::GhostPad::with_target(
+            Some(&format!("{}_recorder_sink", key)),
+            &queue.get_static_pad("sink").unwrap(),
+        )
+        .unwrap();
+        recorder_sink.set_active(true).unwrap();
+        recorder.add_pad(&recorder_sink).unwrap();
+
+        pipeline_gstreamer.pipeline.add(&recorder).unwrap();
+        drop(pipeline_gstreamer);
+        recorder.sync_state_with_parent().unwrap();
+
+        let tee_branch = self.create_tee_branch(key, teebin, recorder_sink.upcast_ref());
+        webrtc::Recorder {
+            bin: recorder,
+            tee_branch,
+            file,
+        }
+    }
+
+    fn add_recorder(
+        &self,
+        recording: &mut webrtc::Recording,
+        key: &str,
+        teebin: &gstreamer::Bin,
+    ) -> bool {
+        if recording.recorders.contains_key(key) {
+            return false;
+        }
+        // a speaker who rejoins gets a new file, the index keeps the names unique
+        let file = match key == RECORDING_MIX {
+            true => "mixed.ogg".to_string(),
+            false => format!(
+                "{}_{}.ogg",
+                recording.manifest.tracks.len() + 1,
+                recording_service::file_name(key)
+            ),
+        };
+        info!(
+            "[ROOM: {}] [UUID: {}] [RECORDING TO: {}]",
+            self.room_name, key, file
+        );
+
+        let location = recording.directory.join(&file);
+        let recorder = self.create_recorder(key, teebin, &location, file.clone());
+        match key == RECORDING_MIX {
+            true => recording.manifest.mixed = Some(file),
+            false => recording.manifest.tracks.push(recording::ManifestTrack {
+                uuid: key.to_string(),
+                file,
+                start_offset_ms: recording.started.elapsed().as_millis() as u64,
+                end_offset_ms: None,
+            }),
+        }
+        recording.recorders.insert(key.to_string(), recorder);
+        self.save_manifest(recording);
+        true
+    }
+
+    fn record_producer(
+        &self,
+        recording: &mut webrtc::Recording,
+        users: &BTreeMap<String, user::User>,
+        uuid: &str,
+    ) -> bool {
+        let user_src = match users.get(uuid) {
+            Some(user_src) => user_src,
+            None => return false,
+        };
+        let recording_track = self.add_recorder(recording, uuid, &user_src.pipeline.tee);
+
+        let mut mixes = self.mixes.lock().unwrap();
+        let mixing = match mixes.get_mut(RECORDING_MIX) {
+            Some(mix) => self.feed_mix(user_src, uuid, RECORDING_MIX, mix),
+            None => false,
+        };
+        recording_track || mixing
+    }
+
+    // without flush the bin is dropped right away, for recorders that already failed
+    fn finish_recorder(
+        &self,
+        recording: &mut webrtc::Recording,
+        key: &str,
+        flush: bool,
+    ) -> Option<oneshot::Receiver<()>> {
+        let recorder = recording.recorders.remove(key)?;
+        info!(
+            "[ROOM: {}] [UUID: {}] [FINISHING RECORDING: {}]",
+            self.room_name, key, recorder.file
+        );
+        let end_offset_ms = recording.started.elapsed().as_millis() as u64;
+        if let Some(track) = recording
+            .manifest
+            .tracks
+            .iter_mut()
+            .find(|track| track.file == recorder.file)
+        {
+            track.end_offset_ms = Some(end_offset_ms);
+        }
+        self.save_manifest(recording);
+
+        self.release_tee_branch(&recorder.tee_branch);
+        match flush {
+            true => Some(self.flush_recorder(key, recorder)),
+            false => {
+                self.release_bin(&recorder.bin);
+                None
+            }
+        }
+    }
+
+    // oggmux only finishes the file on EOS, the bin is removed once EOS reached the filesink
+    fn flush_recorder(&self, key: &str, recorder: webrtc::Recorder) -> oneshot::Receiver<()> {
+        let (flushed, receiver) = oneshot::channel();
+        let flushed = Mutex::new(Some(flushed));
+        let pipeline = self.pipeline_gstreamer.lock().unwrap().pipeline.clone();
+        let room_name = self.room_name.clone();
+        let bin = recorder.bin.clone();
+
+        let filesink_pad = recorder
+            .bin
+            .get_by_name(&format!("{}_recorder_filesink", key))
+            .and_then(|filesink| filesink.get_static_pad("sink"))
+            .unwrap();
+        let eos_probe = filesink_pad
+            .add_probe(
+                gstreamer::PadProbeType::EVENT_DOWNSTREAM,
+                move |_pad, info| {
+                    match &info.data {
+                        Some(gstreamer::PadProbeData::Event(event))
+                            if event.get_type() == gstreamer::EventType::Eos => {}
+                        _ => return gstreamer::PadProbeReturn::Ok,
+                    }
+                    let pipeline = pipeline.clone();
+                    let room_name = room_name.clone();
+                    bin.call_async(move |bin| {
+                        if bin.set_state(gstreamer::State::Null).is_err()
+                            || pipeline.remove(bin).is_err()
+                        {
+                            info!(
+                                "[ROOM: {}] Failed to remove recorder from pipeline",
+                                room_name
+                            );
+                        }
+                    });
+                    if let Some(flushed) = flushed.lock().unwrap().take() {
+                        let _ = flushed.send(());
+                    }
+                    gstreamer::PadProbeReturn::Remove
+                },
+            )
+            .unwrap();
+
+        let recorder_sink = recorder
+            .bin
+            .get_static_pad(&format!("{}_recorder_sink", key))
+            .unwrap();
+        if !recorder_sink.send_event(gstreamer::event::Eos::new()) {
+            info!(
+                "[ROOM: {}] [UUID: {}] [RECORDER DID NOT TAKE EOS]",
+                self.room_name, key
+            );
+            filesink_pad.remove_probe(eos_probe);
+            self.release_bin(&recorder.bin);
+        }
+        receiver
+    }
+
+    fn release_recording_mix(&self) {
+        let mut mixes = self.mixes.lock().unwrap();
+        let mix = match mixes.remove(RECORDING_MIX) {
+            Some(mix) => mix,
+            None => return,
+        };
+        info!(
+            "[ROOM: {}] [RELEASING MIX: {}]",
+            self.room_name, RECORDING_MIX
+        );
+        for mix_input in mix.inputs.values() {
+            self.release_mix_input(&mix, mix_input);
+        }
+        self.release_bin(&mix.bin);
+
+        let mixing: BTreeSet<String> = mixes
+            .values()
+            .flat_map(|mix| mix.inputs.keys().cloned())
+            .collect();
+        drop(mixes);
+
+        let mut decoders = self.decoders.lock().unwrap();
+        let unused: Vec<String> = decoders
+            .keys()
+            .filter(|src| !mixing.contains(*src))
+            .cloned()
+            .collect();
+        for src in unused {
+            let decoder = decoders.remove(&src).unwrap();
+            self.release_tee_branch(&decoder.tee_branch);
+            self.release_bin(&decoder.bin);
+        }
+    }
+
+    // gives up after RECORDING_FLUSH_TIMEOUT so a stuck recorder can't hold the channel
+    fn stop_recording(&self) -> Option<impl ActorFuture<Output = webrtc::Recording, Actor = Self>> {
+        let mut recording = self.recording.lock().unwrap().take()?;
+        info!(
+            "[ROOM: {}] [STOPPING RECORDING: {}]",
+            self.room_name, recording.manifest.id
+        );
+
+        let keys: Vec<String> = recording.recorders.keys().cloned().collect();
+        let flushing: Vec<oneshot::Receiver<()>> = keys
+            .iter()
+            .filter_map(|key| self.finish_recorder(&mut recording, key, true))
+            .collect();
+        self.release_recording_mix();
+
+        Some(
+            future::join_all(flushing)
+                .into_actor(self)
+                .timeout(RECORDING_FLUSH_TIMEOUT)
+                .map(move |flushed, channel, _| {
+                    if flushed.is_err() {
+                        info!(
+                            "[ROOM: {}] [RECORDERS NOT FLUSHED IN {:?}]",
+                            channel.room_name, RECORDING_FLUSH_TIMEOUT
+                        );
+                    }
+                    recording
+                }),
+        )
+    }
+
+    fn complete_recording(&self, mut recording: webrtc::Recording) -> recording::Manifest {
+        info!(
+            "[ROOM: {}] [RECORDING STOPPED: {}]",
+            self.room_name, recording.manifest.id
+        );
+        recording.manifest.stopped_at = Some(recording_service::unix_time());
+        self.save_manifest(&recording);
+        self.broadcast_recording("RecordingStopped", &recording.manifest);
+        recording.manifest
+    }
+
+    // the pipeline goes to Null on stop, so a running recording is flushed first
+    fn stop_channel(&mut self, context: &mut actix::Context<Self>) {
+        if self.stopping {
+            return;
+        }
+        self.stopping = true;
+        // the supervisor forgets this channel right away, so new users get a fresh one
+        self.supervisor_address.do_send(supervisor::ChannelStopped {
+            room_name: self.room_name.clone(),
+            channel_address: context.address(),
+        });
+        match self.stop_recording() {
+            Some(stopping) => {
+                context.spawn(stopping.map(|recording, channel, context| {
+                    channel.complete_recording(recording);
+                    context.stop();
+                }));
+            }
+            None => context.stop(),
+        }
+    }
+
+    fn save_manifest(&self, recording: &webrtc::Recording) {
+        if let Err(error) =
+            recording_service::write_manifest(&recording.directory, &recording.manifest)
+        {
+            info!(
+                "[ROOM: {}] [FAILED TO WRITE RECORDING MANIFEST: {}]",
+                self.room_name, error
+            );
+        }
+    }
+
+    fn broadcast_recording(&self, action: &str, manifest: &recording::Manifest) {
+        let message =
+            serde_json::to_string(&message_websocket::RecordingStatus { action, manifest })
+                .unwrap();
+        self.room_address.do_send(room::Broadcast {
+            room_name: self.room_name.clone(),
+            uuid: "wigglypuff".to_string(),
+            message,
+        });
+    }
+
+    fn release_tee_branch(&self, tee_branch: &webrtc::TeeBranch) {
+        let audio_block = tee_branch
+            .tee_pad
+            .add_probe(gstreamer::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                gstreamer::PadProbeReturn::Ok
+            })
+            .unwrap();
+
+        let queue_sink_pad = tee_branch.queue.get_static_pad("sink").unwrap();
+        if tee_branch.tee_pad.unlink(&queue_sink_pad).is_err() {
+            info!("Failed to unlink tee branch");
+        }
+        tee_branch.tee_pad.remove_probe(audio_block);
+
+        if let Some(tee) = tee_branch.tee_pad.get_parent_element() {
+            tee.release_request_pad(&tee_branch.tee_pad);
+        }
+
+        tee_branch.ghost_pad.set_active(false).unwrap();
+        tee_branch.teebin.remove_pad(&tee_branch.ghost_pad).unwrap();
+        if tee_branch.queue.set_state(gstreamer::State::Null).is_err() {
+            info!("Failed to set tee branch queue to Null");
+        }
+        tee_branch.teebin.remove(&tee_branch.queue).unwrap();
+    }
+
+    fn release_user_pipeline(&self, uuid: &str, user_pipeline: &webrtc::UserPipeline) {
+        info!("[UUID: {}] [RELEASING GSTREAMER PIPELINE]", uuid);
+
+        if let Some(tee_branch) = &user_pipeline.tee_branch {
+            self.release_tee_branch(tee_branch);
+        }
+
+        if let Some(tee_sink_pad) = user_pipeline
+            .tee
+            .get_static_pad(&format!("{}_tee_sink", uuid))
+        {
+            let tee_block = tee_sink_pad
+                .add_probe(gstreamer::PadProbeType::BLOCK_DOWNSTREAM, |_pad, _info| {
+                    gstreamer::PadProbeReturn::Ok
+                })
+                .unwrap();
+            user_pipeline.webrtcbin.unlink(&user_pipeline.tee);
+            user_pipeline.tee.unlink(&user_pipeline.fakesink);
+            tee_sink_pad.remove_probe(tee_block);
+        }
+        user_pipeline.fakeaudio.unlink(&user_pipeline.webrtcbin);
+
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+        for bin in &[
+            &user_pipeline.fakeaudio,
+            &user_pipeline.webrtcbin,
+            &user_pipeline.tee,
+            &user_pipeline.fakesink,
+        ] {
+            if bin.set_state(gstreamer::State::Null).is_err() {
+                info!("[UUID: {}] Failed to set bin to Null", uuid);
+            }
+            if pipeline_gstreamer.pipeline.remove(*bin).is_err() {
+                info!("[UUID: {}] Failed to remove bin from pipeline", uuid);
+            }
+        }
+    }
+}
+
+impl Actor for Channel {
+    type Context = actix::Context<Self>;
+
+    fn started(&mut self, context: &mut Self::Context) {
+        metrics::CHANNELS.inc();
+        self.watch_bus(context);
+        context.run_interval(self.config.stats_interval, |channel, context| {
+            channel.collect_stats(context);
+        });
+        if let Some(detection) = self.config.speaker_detection {
+            context.run_interval(detection.interval, |channel, _| {
+                channel.update_speaker();
+            });
+        }
+    }
+
+    fn stopped(&mut self, context: &mut Self::Context) {
+        info!("[ROOM: {}] [STOPPING CHANNEL]", self.room_name);
+        // only reached with a running recording when the channel wasn't stopped by stop_channel,
+        // its manifest stays unfinished as the files may be cut short
+        if self.stop_recording().is_some() {
+            info!(
+                "[ROOM: {}] [RECORDING NOT FLUSHED BEFORE STOPPING]",
+                self.room_name
+            );
+        }
+        let mut peers = self.peers.lock().unwrap();
+        metrics::CONSUMER_PEERS.sub(peers.len() as i64);
+        peers.clear();
+        drop(peers);
+        let mut users = self.users.lock().unwrap();
+        metrics::PRODUCERS.sub(users.len() as i64);
+        users.clear();
+        drop(users);
+        self.stats.lock().unwrap().clear();
+        self.ready.lock().unwrap().clear();
+        self.mixes.lock().unwrap().clear();
+        self.decoders.lock().unwrap().clear();
+        metrics::CHANNELS.dec();
+
+        let pipeline_gstreamer = self.pipeline_gstreamer.lock().unwrap();
+        if let Some(bus) = pipeline_gstreamer.pipeline.get_bus() {
+            bus.unset_sync_handler();
+        }
+        if pipeline_gstreamer
+            .pipeline
+            .set_state(gstreamer::State::Null)
+            .is_err()
+        {
+            info!("Failed to set pipeline to Null");
+        }
+
+        self.supervisor_address.do_send(supervisor::ChannelStopped {
+            room_name: self.room_name.clone(),
+            channel_address: context.address(),
+        });
+    }
+}
+
+impl Handler<webrtc::RestartICE> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, restart: webrtc::RestartICE, _: &mut actix::Context<Self>) {
+        let _scope = reporting::push_user_scope(&restart.room_name, &restart.from_uuid, "channel");
+        info!(
+            "[ROOM: {}] [FROM UUID: {}] [TARGET: {}] [GET ICE RESTART FROM CHANNEL]",
+            restart.room_name, restart.from_uuid, restart.uuid
+        );
+
+        // only the client on the other end of a connection may restart it
+        let user = if restart.uuid == restart.from_uuid {
+            self.users.lock().unwrap().get(&restart.uuid).cloned()
+        } else if restart
+            .uuid
+            .ends_with(&format!("_sink:{}", restart.from_uuid))
+        {
+            self.peers.lock().unwrap().get(&restart.uuid).cloned()
+        } else {
+            None
+        };
+
+        if let Some(user) = user {
+            // the liveness history belongs to the old ICE session, starting over keeps the
+            // stats collector from releasing the bins while the new session connects
+            self.stats.lock().unwrap().remove(&restart.uuid);
+            user.restart_ice();
+        }
+    }
+}
+
+impl Handler<webrtc::RequestPair> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, user: webrtc::RequestPair, context: &mut actix::Context<Self>) {
+        let _scope = reporting::push_user_scope(&user.room_name, &user.from_uuid, "consumer");
+        info!(
+            "[ROOM: {}] [UUID: {}] [TARGET: {}] [GET PAIR REQUEST FROM CHANNEL]",
+            user.room_name, user.from_uuid, user.uuid
+        );
+        let users = self.users.lock().unwrap();
+        let mut peers = self.peers.lock().unwrap();
+
+        let forward = self.pair(&users, &mut peers, &user.from_uuid, &user.uuid, context);
+        let backward = self.pair(&users, &mut peers, &user.uuid, &user.from_uuid, context);
+        drop(peers);
+        drop(users);
+        if forward || backward {
+            self.play_pipeline();
+        }
+    }
+}
+
+impl Handler<webrtc::AudioLevel> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, audio_level: webrtc::AudioLevel, _: &mut actix::Context<Self>) {
+        if self.users.lock().unwrap().contains_key(&audio_level.uuid) {
+            self.levels
+                .insert(audio_level.uuid, (audio_level.level, Instant::now()));
+        }
+    }
+}
+
+impl Handler<webrtc::ProducerReady> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, producer: webrtc::ProducerReady, context: &mut actix::Context<Self>) {
+        let users = self.users.lock().unwrap();
+        if !users.contains_key(&producer.uuid) {
+            return;
+        }
+        info!(
+            "[ROOM: {}] [UUID: {}] [PRODUCER READY]",
+            self.room_name, producer.uuid
+        );
+        self.ready.lock().unwrap().insert(producer.uuid.clone());
+
+        let mut peers = self.peers.lock().unwrap();
+        let mut paired = false;
+        // a speaker leaves the shared mix for one without their own voice
+        if self.release_track(&users[&producer.uuid], "mix:all", false) {
+            let sources: Vec<String> = self
+                .ready
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|src| self.wants_pair(src, &producer.uuid))
+                .cloned()
+                .collect();
+            for src in sources {
+                paired |= self.pair(&users, &mut peers, &src, &producer.uuid, context);
+            }
+        }
+
+        let sinks: Vec<String> = users
+            .keys()
+            .filter(|sink| self.wants_pair(&producer.uuid, sink))
+            .cloned()
+            .collect();
+        for sink in sinks {
+            paired |= self.pair(&users, &mut peers, &producer.uuid, &sink, context);
+        }
+        if let Some(recording) = self.recording.lock().unwrap().as_mut() {
+            paired |= self.record_producer(recording, &users, &producer.uuid);
+        }
+        drop(peers);
+        drop(users);
+        if paired {
+            self.play_pipeline();
+        }
+    }
+}
+
+impl Handler<supervisor::RegisterUser> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, user: supervisor::RegisterUser, context: &mut actix::Context<Self>) {
+        if self.stopping {
+            // sent before the supervisor forgot this channel, it starts a new one this time
+            self.supervisor_address.do_send(user);
+            return;
+        }
+        if let Some(stop_handle) = self.stop_handle.take() {
+            context.cancel_future(stop_handle);
+        }
+        let _scope = reporting::push_user_scope(&user.room_name, &user.uuid, "producer");
+        let mut users = self.users.lock().unwrap();
+        if users.contains_key(&user.uuid) {
+            info!(
+                "[ROOM: {}] [UUID: {}] [REPLACING EXISTING PIPELINE]",
+                user.room_name, user.uuid
+            );
+            let mut peers = self.peers.lock().unwrap();
+            self.release_user(&mut users, &mut peers, &user.uuid);
+        }
+
+        let user_pipeline = self.build_producer(&user.uuid);
+        let new_user = user::User::new(
+            user.room_address.clone(),
+            context.address(),
+            &user.room_name,
+            &user.uuid,
+            user_pipeline,
+            self.nats.clone(),
+            user.client_offers,
+            self.config
+                .speaker_detection
+                .map(|detection| detection.interval),
+        )
+        .unwrap();
+
+        if user.muted {
+            info!(
+                "[ROOM: {}] [UUID: {}] [JOINED MUTED]",
+                user.room_name, user.uuid
+            );
+            self.set_valve(&user.uuid, &new_user, true);
+        }
+
+        users.insert(user.uuid.clone(), new_user);
+        metrics::PRODUCERS.inc();
+
+        // producers that are already streaming get a consumer towards the newcomer right away
+        let sources: Vec<String> = self
+            .ready
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|src| self.wants_pair(src, &user.uuid))
+            .cloned()
+            .collect();
+        let mut peers = self.peers.lock().unwrap();
+        for src in sources {
+            self.pair(&users, &mut peers, &src, &user.uuid, context);
+        }
+        drop(peers);
+        self.play_pipeline();
+    }
+}
+
+impl Handler<webrtc::SessionDescription> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, sdp: webrtc::SessionDescription, _: &mut actix::Context<Self>) {
+        let _scope = reporting::push_user_scope(&sdp.room_name, &sdp.uuid, "channel");
+        info!(
+            "[ROOM: {}] [FROM UUID: {}] [TO UUID: {}] [GET SDP FROM CHANNEL]",
+            sdp.room_name, sdp.from_uuid, sdp.uuid
+        );
+
+        if sdp.from_uuid != sdp.uuid {
+            if sdp.uuid.contains("_sink") {
+                let peers = self.peers.lock().unwrap();
+                if let Some(peer) = peers.get(&sdp.uuid) {
+                    peer.set_session_to_gstreamer(&sdp.types, sdp.sdp);
+                }
+            }
+        } else {
+            let users = self.users.lock().unwrap();
+            if let Some(user) = users.get(&sdp.from_uuid) {
+                user.set_session_to_gstreamer(&sdp.types, sdp.sdp);
+            }
+        }
+    }
+}
+
+impl Handler<webrtc::ICECandidate> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, ice: webrtc::ICECandidate, _: &mut actix::Context<Self>) {
+        let _scope = reporting::push_user_scope(&ice.room_name, &ice.uuid, "channel");
+        info!(
+            "[ROOM: {}] [FROM UUID: {}] [TO UUID: {}] [GET ICE FROM CHANNEL]",
+            ice.room_name, ice.from_uuid, ice.uuid
+        );
+
+        if ice.from_uuid != ice.uuid {
+            if ice.uuid.contains("_sink") {
+                let peers = self.peers.lock().unwrap();
+                if let Some(peer) = peers.get(&ice.uuid) {
+                    peer.set_ice_to_gstreamer(ice.sdp_mline_index, ice.candidate);
+                }
+            }
+        } else {
+            let users = self.users.lock().unwrap();
+            if let Some(user) = users.get(&ice.from_uuid) {
+                user.set_ice_to_gstreamer(ice.sdp_mline_index, ice.candidate);
+            }
         }
     }
 }
@@ -425,10 +1816,344 @@ impl Handler<webrtc::ICECandidate> for Channel {
 impl Handler<supervisor::DeleteUser> for Channel {
     type Result = ();
 
-    fn handle(&mut self, user: supervisor::DeleteUser, _: &mut actix::Context<Self>) {
+    fn handle(&mut self, user: supervisor::DeleteUser, context: &mut actix::Context<Self>) {
+        let _scope = reporting::push_user_scope(&user.room_name, &user.uuid, "channel");
         info!(
-            "[ROOM: {}] [UUID: {}] [GET user FROM CHANNEL TEST]",
+            "[ROOM: {}] [UUID: {}] [DELETE USER FROM CHANNEL]",
             user.room_name, user.uuid
         );
+        let mut users = self.users.lock().unwrap();
+        let mut peers = self.peers.lock().unwrap();
+        self.release_user(&mut users, &mut peers, &user.uuid);
+
+        if users.is_empty() {
+            info!(
+                "[ROOM: {}] [CHANNEL EMPTY] [STOPPING IN {:?}]",
+                self.room_name, self.config.grace_period
+            );
+            if let Some(stop_handle) = self.stop_handle.take() {
+                context.cancel_future(stop_handle);
+            }
+            let stop_handle = context.run_later(self.config.grace_period, |channel, context| {
+                channel.stop_handle = None;
+                if channel.users.lock().unwrap().is_empty() {
+                    channel.stop_channel(context);
+                }
+            });
+            self.stop_handle = Some(stop_handle);
+        }
+    }
+}
+
+impl Handler<webrtc::Mute> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, mute: webrtc::Mute, _: &mut actix::Context<Self>) {
+        info!(
+            "[ROOM: {}] [TO UUID: {:?}] [MUTE: {}] [GET MUTE FROM CHANNEL]",
+            mute.room_name, mute.uuids, mute.muted
+        );
+        let users = self.users.lock().unwrap();
+        for uuid in &mute.uuids {
+            if let Some(user) = users.get(uuid) {
+                self.set_valve(uuid, user, mute.muted);
+            }
+        }
+    }
+}
+
+impl Handler<webrtc::GetStats> for Channel {
+    type Result = <webrtc::GetStats as actix::Message>::Result;
+
+    fn handle(&mut self, _: webrtc::GetStats, _: &mut actix::Context<Self>) -> Self::Result {
+        let users = self.users.lock().unwrap();
+        let peers = self.peers.lock().unwrap();
+        self.channel_stats(&users, &peers)
+    }
+}
+
+impl Handler<webrtc::ConnectionLost> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, lost: webrtc::ConnectionLost, _: &mut actix::Context<Self>) {
+        info!(
+            "[ROOM: {}] [UUID: {}] [WEBRTC CONNECTION LOST]",
+            self.room_name, lost.uuid
+        );
+
+        if lost.uuid.contains("_sink:") {
+            if self.release_peer(&lost.uuid) {
+                let uuid_split: Vec<&str> = lost.uuid.split("_sink:").collect();
+                self.send_user_status(uuid_split[1], "MediaLost", &uuid_split[0][4..]);
+            }
+        } else if self.users.lock().unwrap().contains_key(&lost.uuid) {
+            self.broadcast_user_status("MediaLost", &lost.uuid);
+        }
+    }
+}
+
+impl Handler<webrtc::PipelineError> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, pipeline_error: webrtc::PipelineError, _: &mut actix::Context<Self>) {
+        let owner = self.element_owner(&pipeline_error.elements);
+        reporting::capture_bus_message(
+            &self.room_name,
+            pipeline_error.elements.first().map_or("", String::as_str),
+            owner.as_deref(),
+            &pipeline_error.error,
+            Level::Error,
+        );
+
+        // a broken recorder ends its own file, it shouldn't take the speaker down with it
+        let recorder = pipeline_error
+            .elements
+            .iter()
+            .find_map(|element| element.strip_suffix("_recorder"));
+        if let Some(key) = recorder {
+            if let Some(recording) = self.recording.lock().unwrap().as_mut() {
+                if recording.recorders.contains_key(key) {
+                    info!(
+                        "[ROOM: {}] [UUID: {}] [GSTREAMER ERROR: {}] [RELEASING RECORDER]",
+                        self.room_name, key, pipeline_error.error
+                    );
+                    self.finish_recorder(recording, key, false);
+                    return;
+                }
+            }
+        }
+
+        let owner = match owner {
+            Some(owner) => owner,
+            None => {
+                info!(
+                    "[ROOM: {}] [UNOWNED GSTREAMER ERROR: {}] [ELEMENTS: {:?}]",
+                    self.room_name, pipeline_error.error, pipeline_error.elements
+                );
+                return;
+            }
+        };
+        info!(
+            "[ROOM: {}] [UUID: {}] [GSTREAMER ERROR: {}] [RELEASING USER BINS]",
+            self.room_name, owner, pipeline_error.error
+        );
+
+        if owner.contains("_sink:") {
+            if self.release_peer(&owner) {
+                let uuid_split: Vec<&str> = owner.split("_sink:").collect();
+                self.send_user_status(uuid_split[1], "PipelineError", &uuid_split[0][4..]);
+            }
+        } else {
+            let mut users = self.users.lock().unwrap();
+            let mut peers = self.peers.lock().unwrap();
+            self.release_user(&mut users, &mut peers, &owner);
+            drop(peers);
+            drop(users);
+
+            self.send_user_status(&owner, "PipelineError", &owner);
+            self.broadcast_user_status("MediaLost", &owner);
+        }
+
+        self.play_pipeline();
+    }
+}
+
+impl Handler<recording::StartRecording> for Channel {
+    type Result = Result<recording::Manifest, error::WigglypuffError>;
+
+    fn handle(
+        &mut self,
+        start: recording::StartRecording,
+        _: &mut actix::Context<Self>,
+    ) -> Self::Result {
+        let users = self.users.lock().unwrap();
+        let mut recording = self.recording.lock().unwrap();
+        if recording.is_some() {
+            return Err(error::WigglypuffError::RecordingAlreadyStarted);
+        }
+
+        let started_at = recording_service::unix_time();
+        let id = started_at.to_string();
+        let directory =
+            recording_service::room_directory(&self.config.recording_directory, &self.room_name)
+                .join(&id);
+        if let Err(error) = fs::create_dir_all(&directory) {
+            info!(
+                "[ROOM: {}] [FAILED TO CREATE RECORDING DIRECTORY: {}]",
+                self.room_name, error
+            );
+            return Err(error::WigglypuffError::RecordingFailed);
+        }
+        info!("[ROOM: {}] [STARTING RECORDING: {}]", self.room_name, id);
+
+        let mut new_recording = webrtc::Recording {
+            manifest: recording::Manifest {
+                room: self.room_name.clone(),
+                id,
+                started_at,
+                stopped_at: None,
+                mixed: None,
+                tracks: Vec::new(),
+            },
+            directory,
+            started: Instant::now(),
+            recorders: BTreeMap::new(),
+        };
+        if start.mixed.unwrap_or(self.config.recording_mixed) {
+            let mut mixes = self.mixes.lock().unwrap();
+            let mix = self.mix_entry(&mut mixes, RECORDING_MIX);
+            self.add_recorder(&mut new_recording, RECORDING_MIX, &mix.bin);
+        }
+        let sources: Vec<String> = self.ready.lock().unwrap().iter().cloned().collect();
+        for src in sources {
+            self.record_producer(&mut new_recording, &users, &src);
+        }
+        self.save_manifest(&new_recording);
+
+        let manifest = new_recording.manifest.clone();
+        *recording = Some(new_recording);
+        drop(recording);
+        drop(users);
+        self.broadcast_recording("RecordingStarted", &manifest);
+        self.play_pipeline();
+        Ok(manifest)
+    }
+}
+
+impl Handler<recording::StopRecording> for Channel {
+    type Result = ResponseActFuture<Self, Result<recording::Manifest, error::WigglypuffError>>;
+
+    fn handle(
+        &mut self,
+        _: recording::StopRecording,
+        _: &mut actix::Context<Self>,
+    ) -> Self::Result {
+        match self.stop_recording() {
+            Some(stopping) => Box::pin(
+                stopping.map(|recording, channel, _| Ok(channel.complete_recording(recording))),
+            ),
+            None => Box::pin(
+                async { Err(error::WigglypuffError::RecordingNotStarted) }.into_actor(self),
+            ),
+        }
     }
 }
+
+impl Handler<supervisor::DeleteRoom> for Channel {
+    type Result = ();
+
+    fn handle(&mut self, room: supervisor::DeleteRoom, context: &mut actix::Context<Self>) {
+        info!("[ROOM: {}] [DELETE ROOM FROM CHANNEL]", room.room_name);
+        self.stop_channel(context);
+    }
+}
+
+fn ice_connection_state(webrtcbin: &gstreamer::Element) -> String {
+    let state = match webrtcbin.get_property("ice-connection-state") {
+        Ok(state) => state
+            .get::<gstreamer_webrtc::WebRTCICEConnectionState>()
+            .unwrap_or(None),
+        Err(_) => None,
+    };
+
+    match state {
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::New) => "new",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Checking) => "checking",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Connected) => "connected",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Completed) => "completed",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Failed) => "failed",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Disconnected) => "disconnected",
+        Some(gstreamer_webrtc::WebRTCICEConnectionState::Closed) => "closed",
+        _ => "unknown",
+    }
+    .to_string()
+}
+
+fn stats_number(stats: &gstreamer::StructureRef, field: &str) -> Option<f64> {
+    let value = stats.get_value(field).ok()?;
+    value
+        .get_some::<u64>()
+        .map(|number| number as f64)
+        .or_else(|_| value.get_some::<i64>().map(|number| number as f64))
+        .or_else(|_| value.get_some::<u32>().map(|number| number as f64))
+        .or_else(|_| value.get_some::<i32>().map(|number| number as f64))
+        .or_else(|_| value.get_some::<f64>())
+        .ok()
+}
+
+fn parse_stats(
+    reply: &gstreamer::StructureRef,
+    ice_connection_state: String,
+) -> webrtc::WebRTCStats {
+    let mut webrtc_stats = webrtc::WebRTCStats {
+        ice_connection_state,
+        ..Default::default()
+    };
+
+    for (_, value) in reply.iter() {
+        let stats = match value.get::<gstreamer::Structure>() {
+            Ok(Some(stats)) => stats,
+            _ => continue,
+        };
+        let number = |field| stats_number(&stats, field);
+        match stats.get_name() {
+            "inbound-rtp" => {
+                webrtc_stats.packets_received += number("packets-received").unwrap_or(0.0) as u64;
+                webrtc_stats.bytes_received += number("bytes-received").unwrap_or(0.0) as u64;
+                webrtc_stats.packets_lost += number("packets-lost").unwrap_or(0.0) as i64;
+                webrtc_stats.jitter = webrtc_stats.jitter.or(number("jitter"));
+            }
+            "outbound-rtp" => {
+                webrtc_stats.packets_sent += number("packets-sent").unwrap_or(0.0) as u64;
+                webrtc_stats.bytes_sent += number("bytes-sent").unwrap_or(0.0) as u64;
+            }
+            "remote-inbound-rtp" => {
+                webrtc_stats.packets_lost += number("packets-lost").unwrap_or(0.0) as i64;
+                webrtc_stats.jitter = webrtc_stats.jitter.or(number("jitter"));
+                webrtc_stats.round_trip_time =
+                    webrtc_stats.round_trip_time.or(number("round-trip-time"));
+            }
+            _ => {}
+        }
+    }
+
+    webrtc_stats
+}
+
+// producers that sent audio must keep sending it, everyone else only has the ICE state to go on
+fn update_liveness(
+    webrtc_stats: &mut webrtc::WebRTCStats,
+    previous: &webrtc::WebRTCStats,
+    expects_media: bool,
+) {
+    let state = webrtc_stats.ice_connection_state.as_str();
+    webrtc_stats.was_connected =
+        previous.was_connected || state == "connected" || state == "completed";
+    webrtc_stats.has_received = previous.has_received || webrtc_stats.bytes_received > 0;
+
+    let is_stalled = state == "disconnected"
+        || (expects_media
+            && previous.was_connected
+            && previous.has_received
+            && webrtc_stats.bytes_received == previous.bytes_received);
+    webrtc_stats.idle_intervals = match is_stalled {
+        true => previous.idle_intervals + 1,
+        false => 0,
+    };
+
+    webrtc_stats.is_alive = !webrtc_stats.was_connected
+        || (state != "failed"
+            && state != "closed"
+            && webrtc_stats.idle_intervals < STATS_DEAD_INTERVALS);
+}
+
+// floored so digital silence stays a number
+fn rms_level(structure: &gstreamer::StructureRef) -> Option<f64> {
+    let rms = structure.get::<glib::ValueArray>("rms").ok().flatten()?;
+    rms.iter()
+        .filter_map(|value| value.get_some::<f64>().ok())
+        .fold(None, |loudest: Option<f64>, level| {
+            Some(loudest.map_or(level, |loudest| loudest.max(level)))
+        })
+        .map(|level| level.max(-127.0))
+}
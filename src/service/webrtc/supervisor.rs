@@ -1,15 +1,19 @@
+use crate::models::error;
 use crate::models::network_transversal;
+use crate::models::recording;
 use crate::models::supervisor;
 use crate::models::webrtc;
 use crate::service::webrtc::channel;
-use actix::{Actor, Addr, Context, Handler};
+use actix::{Actor, Addr, AsyncContext, Context, Handler, ResponseFuture};
 use log::info;
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
 pub struct Supervisor {
     channels: BTreeMap<String, Addr<channel::Channel>>,
+    rooms: BTreeMap<String, supervisor::RoomMedia>,
     nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+    channel_config: channel::ChannelConfig,
 }
 
 impl Actor for Supervisor {
@@ -17,10 +21,15 @@ impl Actor for Supervisor {
 }
 
 impl Supervisor {
-    pub fn new(nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>) -> Addr<Supervisor> {
+    pub fn new(
+        nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+        channel_config: channel::ChannelConfig,
+    ) -> Addr<Supervisor> {
         let supervisor = Supervisor {
             channels: BTreeMap::new(),
-            nats: nats,
+            rooms: BTreeMap::new(),
+            nats,
+            channel_config,
         };
         supervisor.start()
     }
@@ -29,17 +38,22 @@ impl Supervisor {
 impl Handler<supervisor::RegisterUser> for Supervisor {
     type Result = ();
 
-    fn handle(&mut self, user: supervisor::RegisterUser, _: &mut Context<Self>) {
+    fn handle(&mut self, user: supervisor::RegisterUser, context: &mut Context<Self>) {
         let room_name = user.room_name.clone();
-        if !self.channels.contains_key(&room_name) {
-            let nat_address = self.nats.lock().unwrap();
-
-            let nats: Vec<network_transversal::STUNTURN> = nat_address
-                .iter()
-                .map(|room_name| room_name.clone())
-                .collect();
+        let is_running = match self.channels.get(&room_name) {
+            Some(channel) => channel.connected(),
+            None => false,
+        };
 
-            let channel = channel::Channel::new(&room_name.clone(), nats);
+        if !is_running {
+            let channel = channel::Channel::new(
+                &room_name.clone(),
+                self.nats.clone(),
+                user.room_address.clone(),
+                context.address(),
+                self.channel_config.clone(),
+                self.rooms.get(&room_name).cloned().unwrap_or_default(),
+            );
             channel.do_send(user);
             self.channels.insert(room_name.clone(), channel);
         } else {
@@ -50,6 +64,14 @@ impl Handler<supervisor::RegisterUser> for Supervisor {
     }
 }
 
+impl Handler<supervisor::ConfigureRoom> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, room: supervisor::ConfigureRoom, _: &mut Context<Self>) {
+        self.rooms.insert(room.room_name, room.media);
+    }
+}
+
 impl Handler<webrtc::SessionDescription> for Supervisor {
     type Result = ();
 
@@ -82,6 +104,20 @@ impl Handler<webrtc::ICECandidate> for Supervisor {
     }
 }
 
+impl Handler<webrtc::RestartICE> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, restart: webrtc::RestartICE, _: &mut Context<Self>) {
+        info!(
+            "[ROOM: {}] [UUID: {}] [GET ICE RESTART REQUEST] [SEND TO CHANNEL]",
+            restart.room_name, restart.from_uuid
+        );
+        if let Some(channel) = self.channels.get(&restart.room_name) {
+            channel.do_send(restart);
+        }
+    }
+}
+
 impl Handler<webrtc::RequestPair> for Supervisor {
     type Result = ();
 
@@ -96,6 +132,76 @@ impl Handler<webrtc::RequestPair> for Supervisor {
     }
 }
 
+impl Handler<webrtc::Mute> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, mute: webrtc::Mute, _: &mut Context<Self>) {
+        info!(
+            "[ROOM: {}] [UUID: {:?}] [MUTE: {}] [SEND TO CHANNEL]",
+            mute.room_name, mute.uuids, mute.muted
+        );
+        if let Some(channel) = self.channels.get(&mute.room_name) {
+            channel.do_send(mute);
+        }
+    }
+}
+
+impl Handler<webrtc::GetStats> for Supervisor {
+    type Result = ResponseFuture<webrtc::ChannelStats>;
+
+    fn handle(&mut self, stats: webrtc::GetStats, _: &mut Context<Self>) -> Self::Result {
+        let channel = self.channels.get(&stats.name).cloned();
+        Box::pin(async move {
+            match channel {
+                Some(channel) => channel.send(stats).await.unwrap_or_default(),
+                None => webrtc::ChannelStats::default(),
+            }
+        })
+    }
+}
+
+impl Handler<recording::StartRecording> for Supervisor {
+    type Result = ResponseFuture<Result<recording::Manifest, error::WigglypuffError>>;
+
+    fn handle(&mut self, start: recording::StartRecording, _: &mut Context<Self>) -> Self::Result {
+        info!(
+            "[ROOM: {}] [START RECORDING] [SEND TO CHANNEL]",
+            start.room_name
+        );
+        let channel = self.channels.get(&start.room_name).cloned();
+        Box::pin(async move {
+            match channel {
+                Some(channel) => channel.send(start).await?,
+                None => Err(error::WigglypuffError::ChannelNotRunning),
+            }
+        })
+    }
+}
+
+impl Handler<recording::StopRecording> for Supervisor {
+    type Result = ResponseFuture<Result<recording::Manifest, error::WigglypuffError>>;
+
+    fn handle(&mut self, stop: recording::StopRecording, _: &mut Context<Self>) -> Self::Result {
+        info!(
+            "[ROOM: {}] [STOP RECORDING] [SEND TO CHANNEL]",
+            stop.room_name
+        );
+        let channel = self.channels.get(&stop.room_name).cloned();
+        Box::pin(async move {
+            match channel {
+                Some(channel) => channel.send(stop).await?,
+                None => Err(error::WigglypuffError::ChannelNotRunning),
+            }
+        })
+    }
+}
+
+impl Handler<supervisor::Ping> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, _: supervisor::Ping, _: &mut Context<Self>) {}
+}
+
 impl Handler<supervisor::DeleteUser> for Supervisor {
     type Result = ();
 
@@ -109,3 +215,31 @@ impl Handler<supervisor::DeleteUser> for Supervisor {
         }
     }
 }
+
+impl Handler<supervisor::DeleteRoom> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, room: supervisor::DeleteRoom, _: &mut Context<Self>) {
+        info!("[ROOM: {}] [DELETE CHANNEL (ROOM)]", room.room_name);
+        self.rooms.remove(&room.room_name);
+        if let Some(channel) = self.channels.remove(&room.room_name) {
+            channel.do_send(room);
+        }
+    }
+}
+
+impl Handler<supervisor::ChannelStopped> for Supervisor {
+    type Result = ();
+
+    fn handle(&mut self, channel: supervisor::ChannelStopped, _: &mut Context<Self>) {
+        info!("[ROOM: {}] [CHANNEL STOPPED]", channel.room_name);
+        let is_current = match self.channels.get(&channel.room_name) {
+            Some(channel_address) => *channel_address == channel.channel_address,
+            None => false,
+        };
+
+        if is_current {
+            self.channels.remove(&channel.room_name);
+        }
+    }
+}
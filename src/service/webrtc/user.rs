@@ -1,5 +1,8 @@
 use crate::models::{message_websocket, network_transversal, webrtc};
+use crate::service::metrics;
+use crate::service::reporting;
 use crate::service::room as service_room;
+use crate::service::webrtc::channel;
 
 use actix::Addr;
 use anyhow::Error;
@@ -8,7 +11,9 @@ use glib::Value;
 use gstreamer;
 use gstreamer::{prelude::ObjectExt, ElementExt, GstBinExt, PadExt, PadExtManual};
 use log::info;
-use std::sync::{Arc, Weak};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 macro_rules! upgrade_app_weak_reference {
     ($x:ident, $r:expr) => {{
         match $x.upgrade_to_strong_reference() {
@@ -23,10 +28,15 @@ macro_rules! upgrade_app_weak_reference {
 
 pub struct UserInner {
     pub room_address: Addr<service_room::Room>,
+    pub channel_address: Addr<channel::Channel>,
     pub room_name: String,
     pub uuid: String,
     pub pipeline: webrtc::UserPipeline,
-    pub nats: Vec<network_transversal::STUNTURN>,
+    pub nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+    pub negotiation_started: Mutex<Option<Instant>>,
+    pub client_offers: bool,
+    pub level_interval: Option<Duration>,
+    pub tracks: Mutex<BTreeMap<String, webrtc::Track>>,
 }
 
 #[derive(Clone)]
@@ -50,12 +60,16 @@ impl UserWeak {
 }
 
 impl User {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         room_address: Addr<service_room::Room>,
+        channel_address: Addr<channel::Channel>,
         requst_room_name: &String,
         request_uuid: &String,
         pipeline: webrtc::UserPipeline,
-        nats: Vec<network_transversal::STUNTURN>,
+        nats: Arc<Mutex<Vec<network_transversal::STUNTURN>>>,
+        client_offers: bool,
+        level_interval: Option<Duration>,
     ) -> Result<Self, Error> {
         info!(
             "[ROOM: {}] [UUID: {}] [CREATING WEBRTC INSTANCE] ",
@@ -66,10 +80,15 @@ impl User {
         let room_name = requst_room_name.to_string();
         let user = User(Arc::new(UserInner {
             room_address,
+            channel_address,
             room_name,
             uuid,
             pipeline,
             nats,
+            negotiation_started: Mutex::new(None),
+            client_offers,
+            level_interval,
+            tracks: Mutex::new(BTreeMap::new()),
         }));
 
         let user_clone = user.downgrade_to_weak_reference();
@@ -82,7 +101,16 @@ impl User {
         webrtcbin
             .connect("on-negotiation-needed", false, move |_values| {
                 let user = upgrade_app_weak_reference!(user_clone, None);
-                user.on_offer_from_gstreamer();
+                // a client that offers opens the session, renegotiations after that
+                // (new bundled tracks) still start here
+                if user.client_offers && !user.is_negotiated() {
+                    info!(
+                        "[ROOM: {}] [UUID: {}] [WEBRTC] [WAITING FOR CLIENT OFFER]",
+                        user.room_name, user.uuid
+                    );
+                } else {
+                    user.on_offer_from_gstreamer(None);
+                }
                 None
             })
             .unwrap();
@@ -111,19 +139,44 @@ impl User {
         Ok(user)
     }
 
+    fn push_scope(&self) -> sentry::ScopeGuard {
+        reporting::push_user_scope(
+            &self.room_name,
+            &self.uuid,
+            reporting::role_name(&self.pipeline.role),
+        )
+    }
+
     pub fn downgrade_to_weak_reference(&self) -> UserWeak {
         UserWeak(Arc::downgrade(&self.0))
     }
 
-    pub fn set_session_to_gstreamer(&self, session_description_request: String) {
-        let ret = gstreamer_sdp::SDPMessage::parse_buffer(session_description_request.as_bytes())
-            .map_err(|_| info!("Failed to parse SDP offer"))
-            .unwrap();
+    pub fn set_session_to_gstreamer(&self, types: &str, session_description_request: String) {
+        let _scope = self.push_scope();
+        let sdp_type = match types {
+            "offer" => gstreamer_webrtc::WebRTCSDPType::Offer,
+            "answer" => gstreamer_webrtc::WebRTCSDPType::Answer,
+            _ => {
+                info!(
+                    "[ROOM: {}] [UUID: {}] [UNSUPPORTED SDP TYPE: {}]",
+                    self.room_name, self.uuid, types
+                );
+                return;
+            }
+        };
 
-        let answer = gstreamer_webrtc::WebRTCSessionDescription::new(
-            gstreamer_webrtc::WebRTCSDPType::Answer,
-            ret,
-        );
+        let ret =
+            match gstreamer_sdp::SDPMessage::parse_buffer(session_description_request.as_bytes()) {
+                Ok(ret) => ret,
+                Err(_) => {
+                    info!(
+                        "[ROOM: {}] [UUID: {}] [FAILED TO PARSE SDP {}]",
+                        self.room_name, self.uuid, types
+                    );
+                    return;
+                }
+            };
+        let session_description = gstreamer_webrtc::WebRTCSessionDescription::new(sdp_type, ret);
 
         let webrtcbin = self
             .pipeline
@@ -131,15 +184,26 @@ impl User {
             .get_by_name(&format!("{}_webrtcbin", self.uuid))
             .expect("can't find webrtcbin");
 
-        webrtcbin
-            .emit(
-                "set-remote-description",
-                &[&answer, &None::<gstreamer::Promise>],
-            )
-            .unwrap();
+        match sdp_type {
+            gstreamer_webrtc::WebRTCSDPType::Offer => {
+                self.on_offer_from_client(&webrtcbin, &session_description)
+            }
+            _ => {
+                if let Some(negotiation_started) = self.negotiation_started.lock().unwrap().take() {
+                    metrics::SDP_NEGOTIATION.observe(negotiation_started.elapsed().as_secs_f64());
+                }
+                webrtcbin
+                    .emit(
+                        "set-remote-description",
+                        &[&session_description, &None::<gstreamer::Promise>],
+                    )
+                    .unwrap();
+            }
+        }
     }
 
     pub fn set_ice_to_gstreamer(&self, sdp_mline_index: u32, candidate: String) {
+        let _scope = self.push_scope();
         let webrtcbin = self
             .pipeline
             .webrtcbin
@@ -151,15 +215,63 @@ impl User {
             .unwrap();
     }
 
-    fn on_offer_from_gstreamer(&self) {
+    fn is_negotiated(&self) -> bool {
+        let webrtcbin = self
+            .pipeline
+            .webrtcbin
+            .get_by_name(&format!("{}_webrtcbin", self.uuid))
+            .expect("can't find webrtcbin");
+
+        webrtcbin
+            .get_property("current-remote-description")
+            .ok()
+            .and_then(|value| {
+                value
+                    .get::<gstreamer_webrtc::WebRTCSessionDescription>()
+                    .ok()
+            })
+            .flatten()
+            .is_some()
+    }
+
+    pub fn renegotiate(&self) {
+        let _scope = self.push_scope();
+        info!(
+            "[ROOM: {}] [UUID: {}] [WEBRTC] [RENEGOTIATING TRACKS]",
+            self.room_name, self.uuid
+        );
+        self.on_offer_from_gstreamer(None);
+    }
+
+    pub fn restart_ice(&self) {
+        let _scope = self.push_scope();
+        if self.client_offers {
+            info!(
+                "[ROOM: {}] [UUID: {}] [WEBRTC] [ICE RESTART IS LEFT TO THE OFFERING CLIENT]",
+                self.room_name, self.uuid
+            );
+            return;
+        }
+
+        info!(
+            "[ROOM: {}] [UUID: {}] [WEBRTC] [RESTARTING ICE]",
+            self.room_name, self.uuid
+        );
+        let options = gstreamer::Structure::new("options", &[("ice-restart", &true)]);
+        self.on_offer_from_gstreamer(Some(options));
+    }
+
+    fn on_offer_from_gstreamer(&self, options: Option<gstreamer::Structure>) {
+        let _scope = self.push_scope();
         info!(
             "[ROOM: {}] [UUID: {}] [WEBRTC] [STARTING NEGOTIATION]",
             self.room_name, self.uuid
         );
+        *self.negotiation_started.lock().unwrap() = Some(Instant::now());
         let user_clone = self.downgrade_to_weak_reference();
         let promise = gstreamer::Promise::with_change_func(move |reply| {
             let user = upgrade_app_weak_reference!(user_clone);
-            user.on_offer_created(reply);
+            user.on_session_created(reply, "offer");
         });
 
         let webrtcbin = self
@@ -167,96 +279,91 @@ impl User {
             .webrtcbin
             .get_by_name(&format!("{}_webrtcbin", self.uuid))
             .expect("can't find webrtcbin");
+        self.prepare_webrtcbin(&webrtcbin);
 
-        let turn_address_0 = match &self.nats[1] {
-            network_transversal::STUNTURN::TURN {
-                url,
-                urls,
-                username,
-                credential,
-            } => Some(url.clone().replace(
-                "turn:",
-                &format!(
-                    "turn://{username}:{credential}@",
-                    username = username,
-                    credential = credential
-                ),
-            )),
-            _ => None,
-        };
-
-        let turn_address_1 = match &self.nats[2] {
-            network_transversal::STUNTURN::TURN {
-                url,
-                urls,
-                username,
-                credential,
-            } => Some(url.clone().replace(
-                "turn:",
-                &format!(
-                    "turn://{username}:{credential}@",
-                    username = username,
-                    credential = credential
-                ),
-            )),
-            _ => None,
-        };
-
-        let turn_address_2 = match &self.nats[3] {
-            network_transversal::STUNTURN::TURN {
-                url,
-                urls,
-                username,
-                credential,
-            } => Some(url.clone().replace(
-                "turn:",
-                &format!(
-                    "turn://{username}:{credential}@",
-                    username = username,
-                    credential = credential
-                ),
-            )),
-            _ => None,
-        };
-
-        let turn_address_0 = turn_address_0.unwrap();
-        let turn_address_1 = turn_address_1.unwrap();
-        let turn_address_2 = turn_address_2.unwrap();
-        info!("{}", turn_address_1);
-        info!("{}", turn_address_2);
         webrtcbin
-            .emit("add-turn-server", &[&Value::from(turn_address_0.as_str())])
+            .emit("create-offer", &[&options, &promise])
             .unwrap();
+    }
+
+    fn on_offer_from_client(
+        &self,
+        webrtcbin: &gstreamer::Element,
+        offer: &gstreamer_webrtc::WebRTCSessionDescription,
+    ) {
+        // on glare the server keeps its own offer, webrtcbin can't roll a local offer back
+        let signaling_state = webrtcbin
+            .get_property("signaling-state")
+            .ok()
+            .and_then(|value| {
+                value
+                    .get_some::<gstreamer_webrtc::WebRTCSignalingState>()
+                    .ok()
+            });
+        if signaling_state == Some(gstreamer_webrtc::WebRTCSignalingState::HaveLocalOffer) {
+            info!(
+                "[ROOM: {}] [UUID: {}] [WEBRTC] [IGNORING CLIENT OFFER, LOCAL OFFER PENDING]",
+                self.room_name, self.uuid
+            );
+            return;
+        }
+
+        info!(
+            "[ROOM: {}] [UUID: {}] [WEBRTC] [ANSWERING CLIENT OFFER]",
+            self.room_name, self.uuid
+        );
+        *self.negotiation_started.lock().unwrap() = Some(Instant::now());
+        self.prepare_webrtcbin(webrtcbin);
         webrtcbin
-            .emit("add-turn-server", &[&Value::from(turn_address_1.as_str())])
+            .emit(
+                "set-remote-description",
+                &[offer, &None::<gstreamer::Promise>],
+            )
             .unwrap();
+
+        let user_clone = self.downgrade_to_weak_reference();
+        let promise = gstreamer::Promise::with_change_func(move |reply| {
+            let user = upgrade_app_weak_reference!(user_clone);
+            user.on_session_created(reply, "answer");
+        });
         webrtcbin
-            .emit("add-turn-server", &[&Value::from(turn_address_2.as_str())])
+            .emit("create-answer", &[&None::<gstreamer::Structure>, &promise])
             .unwrap();
-        if let Ok(transceiver) = webrtcbin.emit("get-transceiver", &[&0.to_value()]) {
-            if let Some(t) = transceiver {
-                if let Ok(obj) = t.get::<glib::Object>() {
-                    let role = match self.pipeline.role {
-                        webrtc::Role::Consumer {} => {
-                            gstreamer_webrtc::WebRTCRTPTransceiverDirection::Sendonly
-                        }
-                        webrtc::Role::Producer {} => {
-                            gstreamer_webrtc::WebRTCRTPTransceiverDirection::Recvonly
-                        }
-                    };
-                    obj.expect("Error set Transceiver")
-                        .set_property("direction", &role)
-                        .unwrap();
-                }
+    }
+
+    fn prepare_webrtcbin(&self, webrtcbin: &gstreamer::Element) {
+        // read on every negotiation, the refresher keeps replacing expiring credentials
+        let turn_addresses: Vec<String> = self
+            .nats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|nat| nat.turn_uris())
+            .collect();
+        for turn_address in turn_addresses {
+            webrtcbin
+                .emit("add-turn-server", &[&Value::from(turn_address.as_str())])
+                .unwrap();
+        }
+        if let Ok(Some(t)) = webrtcbin.emit("get-transceiver", &[&0.to_value()]) {
+            if let Ok(obj) = t.get::<glib::Object>() {
+                let role = match self.pipeline.role {
+                    webrtc::Role::Consumer => {
+                        gstreamer_webrtc::WebRTCRTPTransceiverDirection::Sendonly
+                    }
+                    webrtc::Role::Producer => {
+                        gstreamer_webrtc::WebRTCRTPTransceiverDirection::Recvonly
+                    }
+                };
+                obj.expect("Error set Transceiver")
+                    .set_property("direction", &role)
+                    .unwrap();
             }
         }
-
-        webrtcbin
-            .emit("create-offer", &[&None::<gstreamer::Structure>, &promise])
-            .unwrap();
     }
 
     fn on_ice_from_gstreamer(&self, candidate: &String, sdp_mline_index: &u32) {
+        let _scope = self.push_scope();
         self.room_address.do_send(webrtc::WigglypuffWebRTC::new(
             &self.uuid,
             &self.room_name,
@@ -269,14 +376,16 @@ impl User {
         ));
     }
 
-    fn on_offer_created(
+    fn on_session_created(
         &self,
         reply: Result<Option<&gstreamer::StructureRef>, gstreamer::PromiseError>,
+        types: &str,
     ) {
+        let _scope = self.push_scope();
         match reply {
             Ok(Some(reply)) => {
-                let offer = reply
-                    .get_value("offer")
+                let session_description = reply
+                    .get_value(types)
                     .unwrap()
                     .get::<gstreamer_webrtc::WebRTCSessionDescription>()
                     .expect("Invalid argument")
@@ -291,9 +400,17 @@ impl User {
                 webrtcbin
                     .emit(
                         "set-local-description",
-                        &[&offer, &None::<gstreamer::Promise>],
+                        &[&session_description, &None::<gstreamer::Promise>],
                     )
                     .unwrap();
+                if types == "answer" {
+                    if let Some(negotiation_started) =
+                        self.negotiation_started.lock().unwrap().take()
+                    {
+                        metrics::SDP_NEGOTIATION
+                            .observe(negotiation_started.elapsed().as_secs_f64());
+                    }
+                }
 
                 self.room_address.do_send(webrtc::WigglypuffWebRTC::new(
                     &self.uuid,
@@ -301,21 +418,109 @@ impl User {
                     self.pipeline.role.clone(),
                     message_websocket::MessageSocketType::SessionDescription {
                         uuid: self.uuid.to_string(),
-                        types: "offer".to_string(),
-                        sdp: offer.get_sdp().as_text().unwrap(),
+                        types: types.to_string(),
+                        sdp: session_description.get_sdp().as_text().unwrap(),
                     },
                 ));
+
+                if session_description.get_sdp().medias_len() > 1 {
+                    self.room_address.do_send(webrtc::WigglypuffWebRTC::new(
+                        &self.uuid,
+                        &self.room_name,
+                        self.pipeline.role.clone(),
+                        message_websocket::MessageSocketType::TrackMap {
+                            uuid: self.uuid.to_string(),
+                            tracks: self.track_map(&webrtcbin, &session_description),
+                        },
+                    ));
+                }
             }
             Ok(None) => {
-                info!("Offer creation future got no reponse");
+                info!("Session description ({}) future got no reponse", types);
+            }
+            Err(err) => {
+                info!(
+                    "Session description ({}) future got error reponse: {:?}",
+                    types, err
+                );
+            }
+        };
+    }
+
+    // transceivers are never removed from webrtcbin, so a transceiver's index is its m-line
+    fn track_map(
+        &self,
+        webrtcbin: &gstreamer::Element,
+        session_description: &gstreamer_webrtc::WebRTCSessionDescription,
+    ) -> BTreeMap<String, String> {
+        let tracks = self.tracks.lock().unwrap();
+        let sdp = session_description.get_sdp();
+        let mut track_map = BTreeMap::new();
+
+        for index in 0..sdp.medias_len() {
+            let transceiver = match webrtcbin.emit("get-transceiver", &[&(index as i32).to_value()])
+            {
+                Ok(Some(transceiver)) => transceiver
+                    .get::<gstreamer_webrtc::WebRTCRTPTransceiver>()
+                    .ok()
+                    .flatten(),
+                _ => None,
+            };
+            let mid = sdp
+                .get_media(index)
+                .and_then(|media| media.get_attribute_val("mid"));
+
+            if let (Some(transceiver), Some(mid)) = (transceiver, mid) {
+                let speaker = tracks
+                    .iter()
+                    .find(|(_, track)| track.transceiver.as_ref() == Some(&transceiver));
+                if let Some((uuid_src, _)) = speaker {
+                    track_map.insert(mid.to_string(), uuid_src.clone());
+                }
             }
+        }
+
+        track_map
+    }
+
+    // the level branch is skipped without the level or opus plugins
+    fn add_level_analysis(
+        &self,
+        rtpopusdepay: &gstreamer::Element,
+        level_interval: Duration,
+    ) -> gstreamer::Pad {
+        let analysis = match gstreamer::parse_bin_from_description(
+            &format!(
+                "queue leaky=downstream ! opusdec ! audioconvert ! level name={uuid}_level interval={interval} post-messages=true ! fakesink sync=false async=false",
+                uuid = self.uuid,
+                interval = level_interval.as_nanos(),
+            ),
+            true,
+        ) {
+            Ok(analysis) => analysis,
             Err(err) => {
-                info!("Offer creation future got error reponse: {:?}", err);
+                info!(
+                    "[ROOM: {}] [UUID: {}] [LEVEL ANALYSIS UNAVAILABLE: {}]",
+                    self.room_name, self.uuid, err
+                );
+                return rtpopusdepay.get_static_pad("src").unwrap();
             }
         };
+        let tee = gstreamer::ElementFactory::make("tee", Some(&format!("{}_leveltee", self.uuid)))
+            .unwrap();
+
+        self.pipeline.webrtcbin.add(&tee).unwrap();
+        self.pipeline.webrtcbin.add(&analysis).unwrap();
+        rtpopusdepay.link(&tee).unwrap();
+        tee.link(&analysis).unwrap();
+        tee.sync_state_with_parent().unwrap();
+        analysis.sync_state_with_parent().unwrap();
+
+        tee.get_request_pad("src_%u").unwrap()
     }
 
     fn on_incoming_stream(&self, pad: &gstreamer::Pad) {
+        let _scope = self.push_scope();
         if pad.get_direction() == gstreamer::PadDirection::Src {
             let rtpopusdepay =
                 gstreamer::ElementFactory::make("rtpopusdepay", Some("source")).unwrap();
@@ -325,9 +530,15 @@ impl User {
             pad.link(&rtpopusdepay.get_static_pad("sink").unwrap())
                 .unwrap();
 
+            let audio_src_pad = match (&self.pipeline.role, self.level_interval) {
+                (webrtc::Role::Producer, Some(level_interval)) => {
+                    self.add_level_analysis(&rtpopusdepay, level_interval)
+                }
+                _ => rtpopusdepay.get_static_pad("src").unwrap(),
+            };
             let rtpopusdepay_src_pad = gstreamer::GhostPad::with_target(
                 Some(&format!("{}_audiosrc", self.uuid)),
-                &rtpopusdepay.get_static_pad("src").unwrap(),
+                &audio_src_pad,
             )
             .unwrap();
             rtpopusdepay_src_pad.set_active(true).unwrap();
@@ -341,14 +552,17 @@ impl User {
             self.pipeline.tee.link(&self.pipeline.fakesink).unwrap();
 
             match self.pipeline.role {
-                webrtc::Role::Consumer {} => {}
-                webrtc::Role::Producer {} => {
+                webrtc::Role::Consumer => {}
+                webrtc::Role::Producer => {
                     self.room_address.do_send(webrtc::WigglypuffWebRTC::new(
                         &self.uuid,
                         &self.room_name,
                         self.pipeline.role.clone(),
                         message_websocket::MessageSocketType::WebRTCConnectionState,
                     ));
+                    self.channel_address.do_send(webrtc::ProducerReady {
+                        uuid: self.uuid.to_string(),
+                    });
                 }
             };
         }
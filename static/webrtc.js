@@ -1,13 +1,15 @@
 var wigglypuffConnection;
 var rtcConfiguration;
+let uuid = makeid(5);
+let room_name = 'dssn';
+let resume_token;
 
 // STUN & TURN SERVER
-fetch(window.location.protocol + "//" + window.location.host + "/api/info/network_transversal")
+fetch(window.location.protocol + "//" + window.location.host + "/api/room/" + room_name + "/ice/" + uuid)
     .then(response => response.json()).then(value=>{
         rtcConfiguration = value;
         console.log(rtcConfiguration);
     });
-let uuid = makeid(5);
 var uuid_new;
 
 class Connection {
@@ -85,6 +87,7 @@ class Connection {
 }
 
 let connections = [];
+let trackMap = {};
 
 function onWigglypuffConnect() {
     console.log("CONNECT");
@@ -96,11 +99,19 @@ function onWigglypuffMessage(event) {
     if (message.data) {
         switch (message.data.action) {
             case "SessionDescription":
-                let connection = new Connection(message.data.uuid, rtcConfiguration, wigglypuffConnection);
-                connections.push(connection);
-                connection.createRTCConnection();
+                // bundled rooms renegotiate the same connection for every new speaker
+                let connection = connections.find(connection => connection.uuid === message.data.uuid);
+                if (!connection) {
+                    connection = new Connection(message.data.uuid, rtcConfiguration, wigglypuffConnection);
+                    connections.push(connection);
+                    connection.createRTCConnection();
+                }
                 connection.setRemoteDescription(message.data, message.data.uuid);
 
+                break;
+            case "TrackMap":
+                trackMap = message.data.tracks;
+                console.log("[TRACK MAP]", trackMap);
                 break;
             case "ICECandidate":
                 let ice_exist = false;
@@ -122,6 +133,9 @@ function onWigglypuffMessage(event) {
     }
 
     if (message.action) {
+        if (message.action === "ResumeToken") {
+            resume_token = message.token;
+        }
         if (message.action === "NewUser") {
             console.log("[NEW]", message.uuid);
             wigglypuffConnection.send(JSON.stringify({ uuid: message.uuid, action: "RequestPair" }));
@@ -138,6 +152,9 @@ function onWigglypuffError(event) {
 
 function onWigglypuffClose(event) {
     console.log("CLOSED");
+    if (resume_token) {
+        setTimeout(wigglypuffConnect, 1000);
+    }
 }
 function makeid(length) {
     var result = '';
@@ -158,8 +175,10 @@ function wigglypuffConnect() {
     }
     new_uri += "//" + loc.host;
 
-    let room_name = 'dssn';
     var wigglypuffUrl = new_uri + '/websocket/' + room_name + '/' + uuid;
+    if (resume_token) {
+        wigglypuffUrl += '?resume=' + resume_token;
+    }
     wigglypuffConnection = new WebSocket(wigglypuffUrl);
     wigglypuffConnection.addEventListener('open', onWigglypuffConnect);
     wigglypuffConnection.addEventListener('error', onWigglypuffError);
@@ -177,7 +196,7 @@ function getVideoElement() {
 }
 
 function onRemoteTrack(event) {
-    console.log("[ON REMOTE TRACK]");
+    console.log("[ON REMOTE TRACK]", trackMap[event.transceiver.mid]);
     if (getVideoElement().srcObject !== event.streams[0]) {
         console.log('Incoming stream');
         getVideoElement().srcObject = event.streams[0];